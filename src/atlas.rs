@@ -0,0 +1,320 @@
+//! A texture atlas that glyphs are rendered into once and drawn from.
+extern crate sdl2;
+extern crate sdl2_ttf;
+
+use std::collections::HashMap;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Renderer, Texture};
+use sdl2_ttf::Font;
+
+use cache::CacheStats;
+
+/// The width and height of an atlas page.
+const PAGE_SIZE: u32 = 1024;
+
+/// The most pages to keep. When they are full, the least recently used
+/// page is replaced.
+const MAX_PAGES: usize = 4;
+
+/// Space left between glyphs so that scaling never samples a neighbour.
+const GLYPH_PADDING: u32 = 1;
+
+/// A row of a page that glyphs of up to its height are put into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next glyph goes.
+    x: u32,
+}
+
+/// Finds space for rectangles on a page, row by row.
+#[derive(Debug, Clone)]
+pub struct ShelfPacker {
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u32, height: u32) -> ShelfPacker {
+        ShelfPacker { width: width, height: height, shelves: Vec::new() }
+    }
+
+    /// Returns where a rectangle of the given size fits, if it does.
+    pub fn pack(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width > self.width {
+            return None;
+        }
+        // Use the lowest shelf that wastes the least height
+        let page_width = self.width;
+        let best = self.shelves.iter_mut()
+            .filter(|shelf| shelf.height >= height
+                && shelf.x + width <= page_width)
+            .min_by_key(|shelf| shelf.height - height);
+        if let Some(shelf) = best {
+            let pos = (shelf.x, shelf.y);
+            shelf.x += width;
+            return Some(pos);
+        }
+        let y = self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height);
+        if y + height > self.height {
+            return None;
+        }
+        self.shelves.push(Shelf { y: y, height: height, x: width });
+        Some((0, y))
+    }
+}
+
+/// Remembers when pages were last used, to pick the page to replace when
+/// there are as many as can be kept.
+#[derive(Debug, Clone, Default)]
+pub struct PageUse {
+    /// When each page was last used.
+    last_used: Vec<u64>,
+    tick: u64,
+}
+
+impl PageUse {
+    /// Marks a page as used now.
+    pub fn touch(&mut self, page: usize) {
+        self.tick += 1;
+        self.last_used[page] = self.tick;
+    }
+
+    /// Returns the index for a new page, which is the least recently used
+    /// page when there are already `max_pages`, and marks it as used.
+    pub fn next_page(&mut self, max_pages: usize) -> usize {
+        let page = if self.last_used.len() < max_pages {
+            self.last_used.push(0);
+            self.last_used.len() - 1
+        } else {
+            (0 .. self.last_used.len())
+                .min_by_key(|&page| self.last_used[page]).unwrap()
+        };
+        self.touch(page);
+        page
+    }
+}
+
+/// Identifies a rasterized glyph. The fonts are those of the current text
+/// style, so the atlas is cleared when the style changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    pub ch: char,
+    /// The position of the font in the font stack.
+    pub font_index: usize,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// Where a glyph is in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    page: usize,
+    rect: Rect,
+}
+
+impl Glyph {
+    pub fn width(&self) -> u32 {
+        self.rect.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.rect.height()
+    }
+}
+
+/// Glyphs rendered in white onto a few large textures, and tinted when drawn.
+/// A glyph larger than a page gets a page of its own size.
+pub struct GlyphAtlas {
+    pages: Vec<Texture>,
+    page_use: PageUse,
+    /// The page that the packer fills, if there is one.
+    packing: Option<usize>,
+    packer: ShelfPacker,
+    texture_bytes: usize,
+    /// The glyphs, or nothing for characters that render to no pixels.
+    glyphs: HashMap<GlyphKey, Option<Glyph>>,
+    stats: CacheStats,
+}
+
+impl GlyphAtlas {
+    pub fn new() -> GlyphAtlas {
+        GlyphAtlas {
+            pages: Vec::new(), page_use: PageUse::default(), packing: None, 
+            packer: ShelfPacker::new(PAGE_SIZE, PAGE_SIZE), texture_bytes: 0,
+            glyphs: HashMap::new(), stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the number of glyphs in the atlas.
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Returns the texture memory used in bytes.
+    pub fn texture_bytes(&self) -> usize {
+        self.texture_bytes
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Removes every glyph, keeping the statistics.
+    pub fn clear(&mut self) {
+        self.stats.evictions += self.glyphs.len() as u64;
+        self.pages.clear();
+        self.page_use = PageUse::default();
+        self.glyphs.clear();
+        self.packing = None;
+        self.texture_bytes = 0;
+    }
+
+    /// Removes the glyphs of a page, and stops packing it.
+    fn evict_page(&mut self, page: usize) {
+        let before = self.glyphs.len();
+        self.glyphs.retain(|_, glyph| glyph.map_or(true, |g| g.page != page));
+        self.stats.evictions += (before - self.glyphs.len()) as u64;
+        if self.packing == Some(page) {
+            self.packing = None;
+        }
+        let query = self.pages[page].query();
+        self.texture_bytes -= (query.width * query.height * 4) as usize;
+    }
+
+    /// Adds a page of the given size, in place of the least recently used
+    /// page when there are as many as can be kept. Returns its index.
+    fn add_page(&mut self, renderer: &mut Renderer, width: u32, height: u32)
+            -> usize {
+        let index = self.page_use.next_page(MAX_PAGES);
+        let mut page = renderer.create_texture_static(PixelFormatEnum::ARGB8888,
+                width, height)
+            .expect("Could not create a glyph atlas page");
+        page.set_blend_mode(BlendMode::Blend);
+        if index < self.pages.len() {
+            self.evict_page(index);
+            self.pages[index] = page;
+        } else {
+            self.pages.push(page);
+        }
+        self.texture_bytes += (width * height * 4) as usize;
+        index
+    }
+
+    /// Returns the page and position where a glyph of the given size goes.
+    fn place(&mut self, renderer: &mut Renderer, width: u32, height: u32) 
+            -> (usize, u32, u32) {
+        if width > PAGE_SIZE || height > PAGE_SIZE {
+            return (self.add_page(renderer, width, height), 0, 0);
+        }
+        if let Some(page) = self.packing {
+            if let Some((x, y)) = self.packer.pack(width, height) {
+                return (page, x, y);
+            }
+        }
+        let page = self.add_page(renderer, PAGE_SIZE, PAGE_SIZE);
+        self.packing = Some(page);
+        self.packer = ShelfPacker::new(PAGE_SIZE, PAGE_SIZE);
+        // Anything no larger than a page fits on an empty one
+        let (x, y) = self.packer.pack(width, height).unwrap();
+        (page, x, y)
+    }
+
+    /// Returns a glyph, rasterizing it with the given font if it isn't in the
+    /// atlas yet. Returns nothing for characters without pixels, like
+    /// zero-width ones. When every page is full the least recently used one
+    /// is replaced.
+    pub fn glyph(&mut self, renderer: &mut Renderer, key: GlyphKey, font: &Font)
+            -> Option<Glyph> {
+        if let Some(&glyph) = self.glyphs.get(&key) {
+            self.stats.hits += 1;
+            if let Some(glyph) = glyph {
+                self.page_use.touch(glyph.page);
+            }
+            return glyph;
+        }
+        self.stats.misses += 1;
+        let rendered = font.render(&key.ch.to_string())
+            .blended(Color::RGBA(255, 255, 255, 255))
+            .ok()
+            .and_then(|surface| {
+                surface.convert_format(PixelFormatEnum::ARGB8888).ok()
+            });
+        let surface = match rendered {
+            Some(surface) => surface,
+            None => {
+                self.glyphs.insert(key, None);
+                return None;
+            },
+        };
+        let (width, height) = surface.size();
+        let (padded_width, padded_height) = (width + GLYPH_PADDING,
+            height + GLYPH_PADDING);
+        let (page_index, x, y) = self.place(renderer, padded_width, 
+            padded_height);
+        let rect = Rect::new(x as i32, y as i32, width, height);
+        let pitch = surface.pitch() as usize;
+        let page = &mut self.pages[page_index];
+        surface.with_lock(|pixels| {
+            page.update(Some(rect), pixels, pitch)
+                .expect("Could not copy a glyph to the atlas");
+        });
+        self.page_use.touch(page_index);
+        let glyph = Glyph { page: page_index, rect: rect };
+        self.glyphs.insert(key, Some(glyph));
+        Some(glyph)
+    }
+
+    /// Draws a glyph in a color with its top left corner at the given point.
+    pub fn draw(&mut self, renderer: &mut Renderer, glyph: Glyph, x: i32, y: i32,
+            color: Color) {
+        let page = &mut self.pages[glyph.page];
+        let (r, g, b, a) = color.rgba();
+        page.set_color_mod(r, g, b);
+        page.set_alpha_mod(a);
+        let target = Rect::new(x, y, glyph.width(), glyph.height());
+        renderer.copy(page, Some(glyph.rect), Some(target));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_rows() {
+        let mut packer = ShelfPacker::new(10, 10);
+        assert_eq!(packer.pack(4, 3), Some((0, 0)));
+        assert_eq!(packer.pack(4, 2), Some((4, 0)));
+        assert_eq!(packer.pack(4, 3), Some((0, 3)));
+        assert_eq!(packer.pack(11, 1), None);
+    }
+
+    #[test]
+    fn test_pack_prefers_tight_shelf() {
+        let mut packer = ShelfPacker::new(10, 10);
+        assert_eq!(packer.pack(8, 5), Some((0, 0)));
+        assert_eq!(packer.pack(4, 2), Some((0, 5)));
+        assert_eq!(packer.pack(2, 2), Some((4, 5)));
+        assert_eq!(packer.pack(2, 4), Some((8, 0)));
+        // A new shelf would go below the last one, where there is no room
+        assert_eq!(packer.pack(2, 4), None);
+    }
+
+    #[test]
+    fn test_replace_least_recently_used_page() {
+        let mut pages = PageUse::default();
+        assert_eq!(pages.next_page(3), 0);
+        assert_eq!(pages.next_page(3), 1);
+        assert_eq!(pages.next_page(3), 2);
+        pages.touch(0);
+        // At the limit, the page used longest ago is replaced
+        assert_eq!(pages.next_page(3), 1);
+        assert_eq!(pages.next_page(3), 2);
+        pages.touch(1);
+        assert_eq!(pages.next_page(3), 0);
+    }
+}
@@ -0,0 +1,248 @@
+//! Rich text attributes attached to the characters of a text field.
+extern crate sdl2;
+
+use sdl2::pixels::Color;
+use cursor::Cursor;
+
+/// How a run of characters is styled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Attributes {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+}
+
+/// A number of consecutive characters sharing the same attributes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AttrRun {
+    pub len: usize,
+    pub attrs: Attributes,
+}
+
+/// Adds a run to the end of the list, merging it with the last run if they
+/// have the same attributes.
+fn push_run(runs: &mut Vec<AttrRun>, len: usize, attrs: Attributes) {
+    if len == 0 {
+        return;
+    }
+    if let Some(last) = runs.last_mut() {
+        if last.attrs == attrs {
+            last.len += len;
+            return;
+        }
+    }
+    runs.push(AttrRun { len: len, attrs: attrs });
+}
+
+/// Splits the runs of a line at the given character column.
+fn split_runs(runs: &[AttrRun], col: usize) -> (Vec<AttrRun>, Vec<AttrRun>) {
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    let mut pos = 0;
+    for run in runs {
+        if pos + run.len <= col {
+            push_run(&mut left, run.len, run.attrs);
+        } else if pos >= col {
+            push_run(&mut right, run.len, run.attrs);
+        } else {
+            push_run(&mut left, col - pos, run.attrs);
+            push_run(&mut right, pos + run.len - col, run.attrs);
+        }
+        pos += run.len;
+    }
+    (left, right)
+}
+
+/// Appends the runs of `other` to `runs`.
+fn append_runs(runs: &mut Vec<AttrRun>, other: Vec<AttrRun>) {
+    for run in other {
+        push_run(runs, run.len, run.attrs);
+    }
+}
+
+/// Attribute runs for every line of a text, kept in sync with its edits.
+/// Columns are counted in characters like `Cursor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributedLines {
+    lines: Vec<Vec<AttrRun>>,
+}
+
+impl AttributedLines {
+    /// Creates plain attributes for the given lines.
+    pub fn new(lines: &[String]) -> AttributedLines {
+        let lines = lines.iter().map(|line| {
+            let mut runs = Vec::new();
+            push_run(&mut runs, line.chars().count(), Attributes::default());
+            runs
+        }).collect();
+        AttributedLines { lines: lines }
+    }
+
+    /// Returns the runs of the given line.
+    pub fn runs(&self, line: usize) -> &[AttrRun] {
+        self.lines.get(line).map_or(&[], |runs| &runs[..])
+    }
+
+    /// Returns the runs of `count` lines from the given one.
+    pub fn line_runs(&self, line: usize, count: usize) -> Vec<Vec<AttrRun>> {
+        self.lines[line .. line + count].to_vec()
+    }
+
+    /// Replaces the runs of `count` lines from the given one with the runs
+    /// of other lines, returning the replaced runs.
+    pub fn replace_lines(&mut self, line: usize, count: usize, 
+            runs: Vec<Vec<AttrRun>>) -> Vec<Vec<AttrRun>> {
+        let replaced = self.lines.drain(line .. line + count).collect();
+        for (i, line_runs) in runs.into_iter().enumerate() {
+            self.lines.insert(line + i, line_runs);
+        }
+        replaced
+    }
+
+    /// Returns the attributes that text typed at the given position gets.
+    pub fn at(&self, pos: Cursor) -> Attributes {
+        let runs = self.runs(pos.line);
+        let mut start = 0;
+        for run in runs {
+            if pos.col <= start + run.len {
+                return run.attrs;
+            }
+            start += run.len;
+        }
+        runs.last().map_or(Attributes::default(), |run| run.attrs)
+    }
+
+    /// Inserts text at the given position with the attributes of the text
+    /// before it, splitting lines at newlines like `Textfield::insert`.
+    pub fn insert(&mut self, at: Cursor, text: &str) {
+        let mut pieces: Vec<_> = text.lines().collect();
+        if text.ends_with('\n') {
+            pieces.push("");
+        }
+        if pieces.is_empty() {
+            return;
+        }
+        let attrs = self.at(at);
+        let (mut left, right) = split_runs(&self.lines[at.line], at.col);
+        push_run(&mut left, pieces[0].chars().count(), attrs);
+        if pieces.len() == 1 {
+            append_runs(&mut left, right);
+            self.lines[at.line] = left;
+        } else {
+            self.lines[at.line] = left;
+            let last = pieces.len() - 1;
+            for (i, piece) in pieces.iter().enumerate().skip(1) {
+                let mut runs = Vec::new();
+                push_run(&mut runs, piece.chars().count(), attrs);
+                if i == last {
+                    append_runs(&mut runs, right.clone());
+                }
+                self.lines.insert(at.line + i, runs);
+            }
+        }
+    }
+
+    /// Deletes the text between two positions.
+    pub fn delete(&mut self, first: Cursor, last: Cursor) {
+        let (mut left, _) = split_runs(&self.lines[first.line], first.col);
+        let (_, right) = split_runs(&self.lines[last.line], last.col);
+        append_runs(&mut left, right);
+        self.lines[first.line] = left;
+        for _ in first.line .. last.line {
+            self.lines.remove(first.line + 1);
+        }
+    }
+
+    /// Changes the attributes of the text between two positions.
+    pub fn apply<F>(&mut self, first: Cursor, last: Cursor, change: F)
+            where F: Fn(&mut Attributes) {
+        for lineno in first.line .. last.line + 1 {
+            let start = if lineno == first.line { first.col } else { 0 };
+            let end = if lineno == last.line { last.col } else { usize::max_value() };
+            let mut runs = Vec::new();
+            let mut pos = 0;
+            for run in &self.lines[lineno] {
+                // Split the run in the parts before, inside and after the range
+                let run_end = pos + run.len;
+                let inner_start = if start > pos { start } else { pos };
+                let inner_end = if end < run_end { end } else { run_end };
+                if inner_start >= inner_end {
+                    push_run(&mut runs, run.len, run.attrs);
+                } else {
+                    let mut changed = run.attrs;
+                    change(&mut changed);
+                    push_run(&mut runs, inner_start - pos, run.attrs);
+                    push_run(&mut runs, inner_end - inner_start, changed);
+                    push_run(&mut runs, run_end - inner_end, run.attrs);
+                }
+                pos = run_end;
+            }
+            self.lines[lineno] = runs;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::Cursor;
+
+    fn bold() -> Attributes {
+        Attributes { bold: true, .. Attributes::default() }
+    }
+
+    fn lens(attrs: &AttributedLines, line: usize) -> Vec<(usize, bool)> {
+        attrs.runs(line).iter().map(|r| (r.len, r.attrs.bold)).collect()
+    }
+
+    fn sample() -> AttributedLines {
+        let lines = vec![String::from("hello world"), String::from("foo")];
+        let mut attrs = AttributedLines::new(&lines);
+        attrs.apply(Cursor::new(0, 6), Cursor::new(0, 11), |a| a.bold = true);
+        attrs
+    }
+
+    #[test]
+    fn test_apply_splits_runs() {
+        let attrs = sample();
+        assert_eq!(lens(&attrs, 0), vec![(6, false), (5, true)]);
+        assert_eq!(lens(&attrs, 1), vec![(3, false)]);
+    }
+
+    #[test]
+    fn test_insert_inherits_previous() {
+        let mut attrs = sample();
+        attrs.insert(Cursor::new(0, 8), "xx");
+        assert_eq!(lens(&attrs, 0), vec![(6, false), (7, true)]);
+        assert_eq!(attrs.at(Cursor::new(0, 6)), Attributes::default());
+        assert_eq!(attrs.at(Cursor::new(0, 7)), bold());
+    }
+
+    #[test]
+    fn test_insert_newlines() {
+        let mut attrs = sample();
+        attrs.insert(Cursor::new(0, 8), "a\nbc\nd");
+        assert_eq!(lens(&attrs, 0), vec![(6, false), (3, true)]);
+        assert_eq!(lens(&attrs, 1), vec![(2, true)]);
+        assert_eq!(lens(&attrs, 2), vec![(4, true)]);
+        assert_eq!(lens(&attrs, 3), vec![(3, false)]);
+    }
+
+    #[test]
+    fn test_delete_across_lines() {
+        let mut attrs = sample();
+        attrs.delete(Cursor::new(0, 4), Cursor::new(1, 1));
+        assert_eq!(lens(&attrs, 0), vec![(6, false)]);
+        assert_eq!(attrs.runs(1).len(), 0);
+    }
+
+    #[test]
+    fn test_delete_within_run() {
+        let mut attrs = sample();
+        attrs.delete(Cursor::new(0, 5), Cursor::new(0, 8));
+        assert_eq!(lens(&attrs, 0), vec![(5, false), (3, true)]);
+    }
+}
@@ -0,0 +1,26 @@
+//! Statistics shared by the caches.
+
+use std::fmt;
+
+/// How well a cache has been doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Returns the fraction of lookups that were hits.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} hits, {} misses ({:.1}% hits), {} evictions",
+            self.hits, self.misses, self.hit_rate() * 100.0, self.evictions)
+    }
+}
@@ -0,0 +1,136 @@
+//! The shape of the caret and its blinking.
+use std::time::{Duration, Instant};
+use sdl2::rect::Rect;
+
+/// How the caret is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaretShape {
+    /// A vertical bar of the given width in front of the character.
+    Beam(u32),
+    /// A box over the character, which is drawn inverted.
+    Block,
+    /// A bar under the character.
+    Underline,
+}
+
+impl Default for CaretShape {
+    fn default() -> CaretShape {
+        CaretShape::Beam(1)
+    }
+}
+
+impl CaretShape {
+    /// Returns the rect of the caret at `x, y` on a line of the given height,
+    /// over a character `char_width` wide.
+    pub fn rect(&self, x: i32, y: i32, char_width: u32, line_height: u32)
+            -> Rect {
+        match *self {
+            CaretShape::Beam(width) => Rect::new(x, y, width, line_height),
+            CaretShape::Block => Rect::new(x, y, char_width, line_height),
+            CaretShape::Underline => {
+                let thickness = ::std::cmp::max(line_height / 10, 2);
+                Rect::new(x, y + (line_height - thickness) as i32, char_width,
+                    thickness)
+            },
+        }
+    }
+}
+
+/// How the caret is drawn in a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaretState {
+    /// The caret is in the off phase of blinking.
+    Hidden,
+    /// The caret is drawn filled in its shape.
+    Solid,
+    /// Only the outline of a block is drawn, while the window isn't focused.
+    Hollow,
+}
+
+/// The blinking of the caret. It is visible for one interval and hidden for
+/// the next, and input starts a visible interval again.
+#[derive(Debug, Clone, Copy)]
+pub struct Blink {
+    interval: Option<Duration>,
+    since: Instant,
+}
+
+impl Blink {
+    /// Creates a blink that starts visible. The caret doesn't blink without
+    /// an interval.
+    pub fn new(interval: Option<Duration>, now: Instant) -> Blink {
+        Blink { interval: interval, since: now }
+    }
+
+    /// Changes the interval and restarts the blinking.
+    pub fn set_interval(&mut self, interval: Option<Duration>, now: Instant) {
+        *self = Blink::new(interval, now);
+    }
+
+    /// Makes the caret visible and starts the interval again.
+    pub fn reset(&mut self, now: Instant) {
+        self.since = now;
+    }
+
+    /// Returns the number of whole intervals that passed.
+    fn phase(&self, now: Instant) -> Option<(u64, Duration)> {
+        let interval = match self.interval {
+            Some(interval) if interval > Duration::from_millis(0) => interval,
+            _ => return None,
+        };
+        let elapsed = now.duration_since(self.since);
+        let nanos = |d: Duration| d.as_secs() * 1_000_000_000
+            + d.subsec_nanos() as u64;
+        let (elapsed, interval) = (nanos(elapsed), nanos(interval));
+        let passed = elapsed / interval;
+        let left = interval - elapsed % interval;
+        Some((passed, Duration::new(left / 1_000_000_000,
+            (left % 1_000_000_000) as u32)))
+    }
+
+    /// Returns whether the caret is in a visible interval.
+    pub fn is_visible(&self, now: Instant) -> bool {
+        self.phase(now).map_or(true, |(passed, _)| passed % 2 == 0)
+    }
+
+    /// Returns how long it is until the caret appears or disappears.
+    pub fn until_toggle(&self, now: Instant) -> Option<Duration> {
+        self.phase(now).map(|(_, left)| left)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use sdl2::rect::Rect;
+
+    #[test]
+    fn test_blink() {
+        let start = Instant::now();
+        let ms = |ms| Duration::from_millis(ms);
+        let mut blink = Blink::new(Some(ms(500)), start);
+        assert!(blink.is_visible(start + ms(499)));
+        assert!(! blink.is_visible(start + ms(500)));
+        assert!(blink.is_visible(start + ms(1200)));
+        assert_eq!(blink.until_toggle(start + ms(1200)), Some(ms(300)));
+        // Input shows the caret again
+        blink.reset(start + ms(700));
+        assert!(blink.is_visible(start + ms(800)));
+        assert!(! blink.is_visible(start + ms(1200)));
+
+        let steady = Blink::new(None, start);
+        assert!(steady.is_visible(start + ms(10000)));
+        assert_eq!(steady.until_toggle(start), None);
+    }
+
+    #[test]
+    fn test_shape_rect() {
+        assert_eq!(CaretShape::Beam(2).rect(10, 20, 8, 16),
+            Rect::new(10, 20, 2, 16));
+        assert_eq!(CaretShape::Block.rect(10, 20, 8, 16),
+            Rect::new(10, 20, 8, 16));
+        assert_eq!(CaretShape::Underline.rect(10, 20, 8, 16),
+            Rect::new(10, 34, 8, 2));
+    }
+}
@@ -0,0 +1,242 @@
+//! Clipboards that text can be copied to and pasted from.
+extern crate sdl2;
+
+use std::env;
+use std::io::{self, Write};
+use std::process::{Child, Command, Stdio};
+use sdl2::VideoSubsystem;
+use sdl2::clipboard::ClipboardUtil;
+
+/// A place to store copied text.
+pub trait Clipboard {
+    /// Returns the text on the clipboard, if there is any.
+    fn get_text(&mut self) -> Option<String>;
+    /// Replaces the text on the clipboard.
+    fn set_text(&mut self, text: &str);
+    /// Sets text that was held back because the clipboard was busy. Returns
+    /// whether text is still waiting to be set.
+    fn flush(&mut self) -> bool { false }
+}
+
+/// A clipboard that only lives inside the editor, for tests and headless use.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    text: String,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> MemoryClipboard {
+        MemoryClipboard::default()
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        if self.text.is_empty() {
+            None
+        } else {
+            Some(self.text.clone())
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.text = String::from(text);
+    }
+}
+
+/// The system clipboard, through SDL.
+pub struct SdlClipboard {
+    util: ClipboardUtil,
+}
+
+impl SdlClipboard {
+    pub fn new(video: &VideoSubsystem) -> SdlClipboard {
+        SdlClipboard { util: video.clipboard() }
+    }
+}
+
+impl Clipboard for SdlClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        if ! self.util.has_clipboard_text() {
+            return None;
+        }
+        self.util.clipboard_text().ok().and_then(|text| {
+            if text.is_empty() { None } else { Some(text) }
+        })
+    }
+
+    fn set_text(&mut self, text: &str) {
+        if let Err(e) = self.util.set_clipboard_text(text) {
+            println!("Could not set the clipboard text: {}", e);
+        }
+    }
+}
+
+/// The display servers that have a primary selection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Display {
+    X11,
+    Wayland,
+}
+
+/// The X11/Wayland primary selection, which holds the selected text and is
+/// pasted with the middle mouse button. SDL has no access to it, so this uses
+/// `xclip` or `wl-copy`/`wl-paste`.
+///
+/// Setting the text doesn't wait for the process. While one is running, only
+/// the latest text is kept and set after it exits, so that dragging a
+/// selection doesn't start a process for every change.
+#[derive(Debug)]
+pub struct PrimarySelection {
+    display: Display,
+    /// The last text set, to avoid starting a process for every selection
+    /// change that doesn't change the text.
+    last_set: Option<String>,
+    /// The process setting the text, until it exited.
+    copying: Option<Child>,
+    /// The text to set once the running process exited.
+    pending: Option<String>,
+    /// Set when the tools couldn't be started, which stops trying.
+    disabled: bool,
+}
+
+impl PrimarySelection {
+    /// Returns the primary selection of the running display server, if it
+    /// has one.
+    pub fn detect() -> Option<PrimarySelection> {
+        let display = if env::var_os("WAYLAND_DISPLAY").is_some() {
+            Display::Wayland
+        } else if env::var_os("DISPLAY").is_some() {
+            Display::X11
+        } else {
+            return None;
+        };
+        Some(PrimarySelection { 
+            display: display, last_set: None, copying: None, pending: None,
+            disabled: false,
+        })
+    }
+
+    fn paste_command(&self) -> Command {
+        match self.display {
+            Display::X11 => {
+                let mut command = Command::new("xclip");
+                command.args(&["-selection", "primary", "-o"]);
+                command
+            },
+            Display::Wayland => {
+                let mut command = Command::new("wl-paste");
+                command.args(&["--primary", "--no-newline"]);
+                command
+            },
+        }
+    }
+
+    fn copy_command(&self) -> Command {
+        match self.display {
+            Display::X11 => {
+                let mut command = Command::new("xclip");
+                command.args(&["-selection", "primary", "-i"]);
+                command
+            },
+            Display::Wayland => {
+                let mut command = Command::new("wl-copy");
+                command.arg("--primary");
+                command
+            },
+        }
+    }
+
+    /// Returns whether the last process setting the text is still running.
+    fn is_copying(&mut self) -> bool {
+        let running = match self.copying {
+            Some(ref mut child) => match child.try_wait() {
+                Ok(None) => true,
+                _ => false,
+            },
+            None => false,
+        };
+        if ! running {
+            self.copying = None;
+        }
+        running
+    }
+
+    /// Stops using the primary selection after the tools failed to start.
+    fn disable(&mut self, e: io::Error) {
+        println!("Could not use the primary selection, disabling it: {}", e);
+        self.disabled = true;
+        self.pending = None;
+    }
+}
+
+impl Clipboard for PrimarySelection {
+    fn get_text(&mut self) -> Option<String> {
+        if self.disabled {
+            return None;
+        }
+        // The selection gets this text as soon as it can
+        if let Some(ref text) = self.pending {
+            return Some(text.clone());
+        }
+        let output = match self.paste_command().stderr(Stdio::null()).output() {
+            Ok(output) => output,
+            Err(e) => {
+                self.disable(e);
+                return None;
+            },
+        };
+        if output.status.success() && ! output.stdout.is_empty() {
+            String::from_utf8(output.stdout).ok()
+        } else {
+            None
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        if self.disabled {
+            return;
+        }
+        let current = self.pending.as_ref().or(self.last_set.as_ref());
+        if current.map_or(false, |last| last == text) {
+            return;
+        }
+        self.pending = Some(String::from(text));
+        self.flush();
+    }
+
+    fn flush(&mut self) -> bool {
+        if self.pending.is_none() || self.is_copying() {
+            return self.pending.is_some();
+        }
+        let text = self.pending.take().unwrap();
+        let child = self.copy_command()
+            .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null())
+            .spawn();
+        match child {
+            Ok(mut child) => {
+                // Closing stdin at the end of the block ends the input
+                if let Some(mut stdin) = child.stdin.take() {
+                    let _ = stdin.write_all(text.as_bytes());
+                }
+                self.copying = Some(child);
+                self.last_set = Some(text);
+            },
+            Err(e) => self.disable(e),
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_clipboard() {
+        let mut clipboard = MemoryClipboard::new();
+        assert_eq!(clipboard.get_text(), None);
+        clipboard.set_text("hello");
+        assert_eq!(clipboard.get_text(), Some(String::from("hello")));
+    }
+}
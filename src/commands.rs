@@ -0,0 +1,699 @@
+//! Named editing commands.
+
+use std::collections::HashMap;
+use cursor::Cursor;
+use common::StringSliceExt;
+use editor::Editor;
+
+/// An operation on the editor that can be bound to keys.
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub run: fn(&mut Editor),
+}
+
+/// The commands that are always available.
+const BUILTIN: [Command; 36] = [
+    Command { name: "quit", description: "Close the editor",
+        run: quit },
+    Command { name: "move_left", description: "Move the cursor left",
+        run: move_left },
+    Command { name: "move_right", description: "Move the cursor right",
+        run: move_right },
+    Command { name: "move_up", description: "Move the cursor up",
+        run: move_up },
+    Command { name: "move_down", description: "Move the cursor down",
+        run: move_down },
+    Command { name: "move_home", 
+        description: "Move the cursor to the start of the visual line",
+        run: move_home },
+    Command { name: "move_end", 
+        description: "Move the cursor to the end of the visual line",
+        run: move_end },
+    Command { name: "select_left", description: "Extend the selection left",
+        run: select_left },
+    Command { name: "select_right", description: "Extend the selection right",
+        run: select_right },
+    Command { name: "select_up", description: "Extend the selection up",
+        run: select_up },
+    Command { name: "select_down", description: "Extend the selection down",
+        run: select_down },
+    Command { name: "select_home", 
+        description: "Extend the selection to the start of the visual line",
+        run: select_home },
+    Command { name: "select_end", 
+        description: "Extend the selection to the end of the visual line",
+        run: select_end },
+    Command { name: "delete_previous", 
+        description: "Delete the selection or the character before the cursor",
+        run: delete_previous },
+    Command { name: "delete_next",
+        description: "Delete the selection or the character after the cursor",
+        run: delete_next },
+    Command { name: "undo", description: "Revert the last edit",
+        run: undo },
+    Command { name: "redo", description: "Make the last undone edit again",
+        run: redo },
+    Command { name: "toggle_overwrite", 
+        description: "Switch between inserting and overwriting text",
+        run: toggle_overwrite },
+    Command { name: "newline", description: "Insert a line break",
+        run: newline },
+    Command { name: "indent", description: "Insert indentation to the next tab stop",
+        run: indent },
+    Command { name: "copy", description: "Copy the selection",
+        run: copy },
+    Command { name: "cut", description: "Cut the selection",
+        run: cut },
+    Command { name: "paste", description: "Paste over the selection",
+        run: paste },
+    Command { name: "paste_primary",
+        description: "Paste the primary selection over the selection",
+        run: paste_primary },
+    Command { name: "kill_line",
+        description: "Kill the selection or the rest of the line",
+        run: kill_line },
+    Command { name: "kill_line_backward",
+        description: "Kill the selection or the line before the cursor",
+        run: kill_line_backward },
+    Command { name: "yank", description: "Insert the last killed text",
+        run: yank },
+    Command { name: "yank_pop",
+        description: "Replace the text just yanked with an older kill",
+        run: yank_pop },
+    Command { name: "choose_register",
+        description: "Use the register named by the next typed character",
+        run: choose_register },
+    Command { name: "toggle_bold", description: "Toggle bold on the selection",
+        run: toggle_bold },
+    Command { name: "toggle_italic", 
+        description: "Toggle italic on the selection",
+        run: toggle_italic },
+    Command { name: "toggle_underline", 
+        description: "Toggle underline on the selection",
+        run: toggle_underline },
+    Command { name: "toggle_strikethrough", 
+        description: "Toggle strikethrough on the selection",
+        run: toggle_strikethrough },
+    Command { name: "print_clipboard", 
+        description: "Print the clipboard to the terminal",
+        run: print_clipboard },
+    Command { name: "select_none", description: "Clear the selection",
+        run: select_none },
+    Command { name: "cycle_wrap", 
+        description: "Switch to the next way of wrapping lines",
+        run: cycle_wrap },
+];
+
+fn quit(editor: &mut Editor) { editor.quit = true; }
+fn move_left(editor: &mut Editor) { editor.field.left(); }
+fn move_right(editor: &mut Editor) { editor.field.right(); }
+fn move_up(editor: &mut Editor) { editor.move_vertically(false, false); }
+fn move_down(editor: &mut Editor) { editor.move_vertically(true, false); }
+fn move_home(editor: &mut Editor) { editor.move_to_line_edge(false, false); }
+fn move_end(editor: &mut Editor) { editor.move_to_line_edge(true, false); }
+fn select_left(editor: &mut Editor) { editor.field.select_left(); }
+fn select_right(editor: &mut Editor) { editor.field.select_right(); }
+fn select_up(editor: &mut Editor) { editor.move_vertically(false, true); }
+fn select_down(editor: &mut Editor) { editor.move_vertically(true, true); }
+fn select_home(editor: &mut Editor) { editor.move_to_line_edge(false, true); }
+fn select_end(editor: &mut Editor) { editor.move_to_line_edge(true, true); }
+fn select_none(editor: &mut Editor) { editor.field.clear_selection(); }
+fn undo(editor: &mut Editor) { editor.field.undo(); }
+fn redo(editor: &mut Editor) { editor.field.redo(); }
+fn newline(editor: &mut Editor) { editor.field.insert("\n"); }
+
+fn toggle_overwrite(editor: &mut Editor) {
+    editor.field.overwrite = ! editor.field.overwrite;
+    println!("Overwrite: {}", editor.field.overwrite);
+}
+
+fn cycle_wrap(editor: &mut Editor) {
+    editor.wrap = editor.wrap.next();
+    println!("Wrapping: {:?}", editor.wrap);
+}
+
+fn indent(editor: &mut Editor) {
+    if editor.tabs.expand {
+        let cursor = editor.field.cons_cursor();
+        let before = editor.field.lines[cursor.line].slice_until(cursor.col);
+        let col = editor.tabs.visual_col(before);
+        let width = editor.tabs.width;
+        editor.field.insert(&" ".repeat(width - col % width));
+    } else {
+        editor.field.insert("\t");
+    }
+}
+
+/// Stores copied text in the chosen register, or on the clipboard.
+fn store(editor: &mut Editor, text: &str) {
+    match editor.register {
+        Some(name) => editor.registers.set(name, text),
+        None => editor.clipboard.set_text(text),
+    }
+}
+
+fn copy(editor: &mut Editor) {
+    if editor.field.has_selection() {
+        let text = editor.field.selected_text();
+        store(editor, &text);
+    }
+}
+
+fn cut(editor: &mut Editor) {
+    if editor.field.has_selection() {
+        let text = editor.field.selected_text();
+        store(editor, &text);
+        editor.field.delete_selection();
+        if editor.register.is_none() {
+            add_kill(editor, &text, false);
+        }
+    }
+}
+
+fn paste(editor: &mut Editor) {
+    let text = match editor.register {
+        Some(name) => editor.registers.get(name).map(String::from),
+        None => editor.clipboard.get_text(),
+    };
+    if let Some(text) = text {
+        editor.field.insert(&text);
+    }
+}
+
+fn paste_primary(editor: &mut Editor) {
+    let text = editor.primary.as_mut().and_then(|primary| primary.get_text());
+    if let Some(text) = text {
+        editor.field.insert(&text);
+    }
+}
+
+/// The commands that put the text they delete in the kill ring.
+const KILLING_COMMANDS: [&'static str; 5] = [
+    "kill_line", "kill_line_backward", "cut", "delete_previous", "delete_next",
+];
+
+/// Adds killed text to the kill ring. Text killed right after other killed
+/// text is added to the same entry, before it when killing backward.
+fn add_kill(editor: &mut Editor, text: &str, backward: bool) {
+    if text.is_empty() {
+        return;
+    }
+    let continues = editor.last_command
+        .map_or(false, |name| KILLING_COMMANDS.contains(&name));
+    if ! continues {
+        editor.kill_ring.push(text);
+    } else if backward {
+        editor.kill_ring.prepend(text);
+    } else {
+        editor.kill_ring.append(text);
+    }
+}
+
+/// Puts deleted text in the chosen register, or adds it to the kill ring.
+fn save_deleted(editor: &mut Editor, text: &str, backward: bool) {
+    match editor.register {
+        Some(name) => editor.registers.set(name, text),
+        None => add_kill(editor, text, backward),
+    }
+}
+
+/// Deletes the selection and saves it in the kill ring, or in the chosen
+/// register.
+fn kill(editor: &mut Editor, backward: bool) {
+    if ! editor.field.has_selection() {
+        return;
+    }
+    let text = editor.field.selected_text();
+    editor.field.delete_selection();
+    save_deleted(editor, &text, backward);
+}
+
+/// Returns the text that deleting the selection, or the character before or
+/// after the cursor, removes.
+fn deleted_text(editor: &mut Editor, forward: bool) -> String {
+    if editor.field.has_selection() {
+        return editor.field.selected_text();
+    }
+    let field = &editor.field;
+    let cursor = field.cons_cursor();
+    let line = &field.lines[cursor.line];
+    let ch = if forward {
+        line.chars().nth(cursor.col)
+    } else if cursor.col > 0 {
+        line.chars().nth(cursor.col - 1)
+    } else {
+        None
+    };
+    match ch {
+        Some(ch) => ch.to_string(),
+        // Deleting at the edge of a line joins it with the next or previous
+        None if forward && cursor.line + 1 < field.lines.len() => 
+            String::from("\n"),
+        None if ! forward && cursor.line > 0 => String::from("\n"),
+        None => String::new(),
+    }
+}
+
+fn delete_previous(editor: &mut Editor) {
+    let text = deleted_text(editor, false);
+    editor.field.delete_previous();
+    save_deleted(editor, &text, true);
+}
+
+fn delete_next(editor: &mut Editor) {
+    let text = deleted_text(editor, true);
+    editor.field.delete_next();
+    save_deleted(editor, &text, false);
+}
+
+fn kill_line(editor: &mut Editor) {
+    if ! editor.field.has_selection() {
+        let cursor = editor.field.cons_cursor();
+        let len = editor.field.lines[cursor.line].chars().count();
+        // At the end of a line the line break is killed
+        let end = if cursor.col < len {
+            Cursor::new(cursor.line, len)
+        } else if cursor.line + 1 < editor.field.lines.len() {
+            Cursor::new(cursor.line + 1, 0)
+        } else {
+            return;
+        };
+        editor.field.selection_marker = cursor;
+        editor.field.cursor = end;
+    }
+    kill(editor, false);
+}
+
+fn kill_line_backward(editor: &mut Editor) {
+    if ! editor.field.has_selection() {
+        let cursor = editor.field.cons_cursor();
+        let start = if cursor.col > 0 {
+            Cursor::new(cursor.line, 0)
+        } else if cursor.line > 0 {
+            let len = editor.field.lines[cursor.line - 1].chars().count();
+            Cursor::new(cursor.line - 1, len)
+        } else {
+            return;
+        };
+        editor.field.selection_marker = cursor;
+        editor.field.cursor = start;
+    }
+    kill(editor, true);
+}
+
+/// Inserts yanked text over the selection and remembers where it went.
+fn insert_yanked(editor: &mut Editor, text: &str) {
+    editor.field.delete_selection();
+    let start = editor.field.cons_cursor();
+    editor.field.insert(text);
+    editor.last_yank = Some((start, editor.field.cons_cursor()));
+}
+
+fn yank(editor: &mut Editor) {
+    let text = match editor.register {
+        Some(name) => editor.registers.get(name).map(String::from),
+        None => editor.kill_ring.yank().map(String::from),
+    };
+    if let Some(text) = text {
+        insert_yanked(editor, &text);
+    }
+}
+
+fn yank_pop(editor: &mut Editor) {
+    let (start, end) = match (editor.last_command, editor.last_yank.clone()) {
+        (Some("yank"), Some(range)) | (Some("yank_pop"), Some(range)) => range,
+        _ => return,
+    };
+    let text = match editor.kill_ring.yank_pop() {
+        Some(text) => String::from(text),
+        None => return,
+    };
+    editor.field.selection_marker = start;
+    editor.field.cursor = end;
+    insert_yanked(editor, &text);
+}
+
+fn choose_register(editor: &mut Editor) { editor.awaiting_register = true; }
+
+fn toggle_bold(editor: &mut Editor) {
+    editor.field.enable_attributes();
+    editor.field.apply_attributes(|a| a.bold = !a.bold);
+}
+
+fn toggle_italic(editor: &mut Editor) {
+    editor.field.enable_attributes();
+    editor.field.apply_attributes(|a| a.italic = !a.italic);
+}
+
+fn toggle_underline(editor: &mut Editor) {
+    editor.field.enable_attributes();
+    editor.field.apply_attributes(|a| a.underline = !a.underline);
+}
+
+fn toggle_strikethrough(editor: &mut Editor) {
+    editor.field.enable_attributes();
+    editor.field.apply_attributes(|a| a.strikethrough = !a.strikethrough);
+}
+
+fn print_clipboard(editor: &mut Editor) {
+    println!("Text:");
+    println!("{}", editor.clipboard.get_text().unwrap_or_default());
+}
+
+/// Returns whether a command with the given name is built in.
+pub fn is_builtin(name: &str) -> bool {
+    BUILTIN.iter().any(|command| command.name == name)
+}
+
+/// The commands that can be run by name.
+#[derive(Clone)]
+pub struct CommandRegistry {
+    commands: HashMap<&'static str, Command>,
+}
+
+impl CommandRegistry {
+    /// Creates a registry with the built-in commands.
+    pub fn builtin() -> CommandRegistry {
+        let mut registry = CommandRegistry { commands: HashMap::new() };
+        for command in BUILTIN.iter() {
+            registry.register(*command);
+        }
+        registry
+    }
+
+    /// Adds a command, replacing any command with the same name.
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    /// Finds a command by name.
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.get(name)
+    }
+
+    /// Runs the named command. Returns false if there is no such command.
+    pub fn run(&self, name: &str, editor: &mut Editor) -> bool {
+        match self.commands.get(name) {
+            Some(command) => {
+                editor.awaiting_register = false;
+                (command.run)(editor);
+                editor.finish_command(command.name);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use editor::Editor;
+    use textfield::Textfield;
+    use config::TabConfig;
+    use clipboard::{Clipboard, MemoryClipboard};
+    use layout::WrapMode;
+    use measure::{FontRun, TextMeasurer};
+
+    fn editor(text: &str) -> Editor {
+        Editor::new(Textfield::new(text), TabConfig { width: 4, expand: true })
+    }
+
+    #[test]
+    fn test_run_unknown() {
+        let registry = CommandRegistry::builtin();
+        assert!(! registry.run("no_such_command", &mut editor("abc")));
+    }
+
+    #[test]
+    fn test_cut_and_paste() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("abc");
+        registry.run("select_right", &mut editor);
+        registry.run("select_right", &mut editor);
+        registry.run("cut", &mut editor);
+        assert_eq!(editor.field.lines, vec!["c"]);
+        registry.run("move_right", &mut editor);
+        registry.run("paste", &mut editor);
+        assert_eq!(editor.field.lines, vec!["cab"]);
+    }
+
+    #[test]
+    fn test_paste_primary() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("ab");
+        registry.run("paste_primary", &mut editor);
+        assert_eq!(editor.field.lines, vec!["ab"]);
+        let mut primary = MemoryClipboard::new();
+        primary.set_text("x");
+        editor.primary = Some(Box::new(primary));
+        registry.run("paste_primary", &mut editor);
+        assert_eq!(editor.field.lines, vec!["xab"]);
+    }
+
+    #[test]
+    fn test_consecutive_kills_append() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("ab\ncd");
+        registry.run("move_right", &mut editor);
+        registry.run("kill_line", &mut editor);
+        registry.run("kill_line", &mut editor);
+        registry.run("kill_line", &mut editor);
+        assert_eq!(editor.field.lines, vec!["a"]);
+        registry.run("yank", &mut editor);
+        assert_eq!(editor.field.lines, vec!["ab", "cd"]);
+    }
+
+    #[test]
+    fn test_deletions_and_cut_kill() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("abc\nde");
+        registry.run("move_down", &mut editor);
+        registry.run("delete_previous", &mut editor);
+        registry.run("delete_previous", &mut editor);
+        registry.run("delete_next", &mut editor);
+        assert_eq!(editor.field.lines, vec!["abe"]);
+        assert_eq!(editor.kill_ring.yank(), Some("c\nd"));
+        // Cutting after moving starts a new entry
+        registry.run("move_left", &mut editor);
+        registry.run("select_left", &mut editor);
+        registry.run("cut", &mut editor);
+        assert_eq!(editor.field.lines, vec!["be"]);
+        assert_eq!(editor.kill_ring.yank(), Some("a"));
+        assert_eq!(editor.kill_ring.len(), 2);
+    }
+
+    #[test]
+    fn test_yank_pop_cycles() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("one\ntwo");
+        registry.run("kill_line", &mut editor);
+        registry.run("move_down", &mut editor);
+        registry.run("kill_line", &mut editor);
+        registry.run("yank", &mut editor);
+        assert_eq!(editor.field.lines, vec!["", "two"]);
+        registry.run("yank_pop", &mut editor);
+        assert_eq!(editor.field.lines, vec!["", "one"]);
+        registry.run("move_left", &mut editor);
+        registry.run("yank_pop", &mut editor);
+        assert_eq!(editor.field.lines, vec!["", "one"]);
+    }
+
+    #[test]
+    fn test_named_register() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("ab");
+        registry.run("select_right", &mut editor);
+        registry.run("choose_register", &mut editor);
+        editor.input_text("q");
+        registry.run("copy", &mut editor);
+        assert_eq!(editor.registers.get('q'), Some("a"));
+        assert_eq!(editor.clipboard.get_text(), None);
+        registry.run("move_right", &mut editor);
+        registry.run("paste", &mut editor);
+        assert_eq!(editor.field.lines, vec!["ab"]);
+        registry.run("choose_register", &mut editor);
+        editor.input_text("q");
+        registry.run("paste", &mut editor);
+        assert_eq!(editor.field.lines, vec!["aab"]);
+    }
+
+    #[test]
+    fn test_composition_is_committed_by_input() {
+        let mut editor = editor("ab");
+        editor.compose("にほ", 1);
+        assert_eq!(editor.field.lines, vec!["ab"]);
+        assert_eq!(editor.composition.as_ref().unwrap().cursor, 1);
+        editor.input_text("日本");
+        assert_eq!(editor.composition, None);
+        assert_eq!(editor.field.lines, vec!["日本ab"]);
+        editor.compose("x", 5);
+        assert_eq!(editor.composition.as_ref().unwrap().cursor, 1);
+        editor.compose("", 0);
+        assert_eq!(editor.composition, None);
+    }
+
+    #[test]
+    fn test_indent_to_tab_stop() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("ab");
+        registry.run("move_right", &mut editor);
+        registry.run("indent", &mut editor);
+        assert_eq!(editor.field.lines, vec!["a   b"]);
+        // Tabs before the cursor reach to their tab stop
+        editor.field = Textfield::new("\tab");
+        editor.field.cursor = Cursor::new(0, 3);
+        editor.field.clear_selection();
+        registry.run("indent", &mut editor);
+        assert_eq!(editor.field.lines, vec!["\tab  "]);
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("abc\nd");
+        registry.run("toggle_overwrite", &mut editor);
+        editor.input_text("x");
+        editor.input_text("yz");
+        assert_eq!(editor.field.lines, vec!["xyz", "d"]);
+        // The line end isn't replaced
+        editor.input_text("w");
+        registry.run("newline", &mut editor);
+        assert_eq!(editor.field.lines, vec!["xyzw", "", "d"]);
+        registry.run("toggle_overwrite", &mut editor);
+        editor.input_text("e");
+        assert_eq!(editor.field.lines, vec!["xyzw", "e", "d"]);
+    }
+
+    #[test]
+    fn test_undo_restores_overwritten_text() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("abcd");
+        registry.run("move_right", &mut editor);
+        registry.run("toggle_overwrite", &mut editor);
+        editor.input_text("xy");
+        assert_eq!(editor.field.lines, vec!["axyd"]);
+        registry.run("undo", &mut editor);
+        assert_eq!(editor.field.lines, vec!["abcd"]);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 1));
+        assert!(! editor.field.has_selection());
+        registry.run("redo", &mut editor);
+        assert_eq!(editor.field.lines, vec!["axyd"]);
+    }
+
+    #[test]
+    fn test_undo_redo_lines() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("ab\ncd");
+        registry.run("select_down", &mut editor);
+        editor.input_text("x\ny\nz");
+        registry.run("delete_previous", &mut editor);
+        assert_eq!(editor.field.lines, vec!["x", "y", "cd"]);
+        registry.run("undo", &mut editor);
+        assert_eq!(editor.field.lines, vec!["x", "y", "zcd"]);
+        registry.run("undo", &mut editor);
+        assert_eq!(editor.field.lines, vec!["ab", "cd"]);
+        assert_eq!(editor.field.selection_marker, Cursor::new(1, 0));
+        registry.run("redo", &mut editor);
+        registry.run("redo", &mut editor);
+        assert_eq!(editor.field.lines, vec!["x", "y", "cd"]);
+        // A new edit drops what was undone
+        registry.run("undo", &mut editor);
+        editor.input_text("q");
+        assert!(! editor.field.redo());
+    }
+
+    #[test]
+    fn test_move_by_visual_lines() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("aaaa bbbb cc\nxy");
+        editor.wrap = WrapMode::Viewport;
+        editor.view_width = 5;
+        editor.field.cursor = Cursor::new(0, 2);
+        registry.run("move_down", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 7));
+        registry.run("move_down", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 12));
+        registry.run("move_down", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(1, 2));
+        registry.run("move_up", &mut editor);
+        registry.run("move_home", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 10));
+        registry.run("select_up", &mut editor);
+        assert_eq!(editor.field.selected_text(), "bbbb ");
+    }
+
+    #[test]
+    fn test_end_of_visual_line_stays_before_wrap() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("aaaa bbbb cc");
+        editor.wrap = WrapMode::Viewport;
+        editor.view_width = 5;
+        registry.run("move_end", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 5));
+        assert_eq!(editor.field.wrap_end, Some(Cursor::new(0, 5)));
+        registry.run("move_down", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 10));
+        registry.run("move_home", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 5));
+        assert_eq!(editor.field.wrap_end, None);
+    }
+
+    /// Measures bold text twice as wide as regular text.
+    struct WideBold;
+
+    impl TextMeasurer for WideBold {
+        fn width(&self, text: &str) -> u32 { text.chars().count() as u32 }
+        fn styled_width(&self, text: &str, bold: bool, _: bool) -> u32 {
+            text.chars().count() as u32 * if bold { 2 } else { 1 }
+        }
+        fn advance(&self, _: char) -> u32 { 1 }
+        fn line_height(&self) -> u32 { 1 }
+        fn ascent(&self) -> i32 { 1 }
+        fn descent(&self) -> i32 { 0 }
+    }
+
+    #[test]
+    fn test_moves_measure_like_the_view() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("aaaa bbbb");
+        editor.wrap = WrapMode::Viewport;
+        editor.view_width = 10;
+        editor.measurer = Box::new(WideBold);
+        registry.run("move_end", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 9));
+        // Bold text is wider, so the line wraps
+        editor.font_runs = Box::new(|field, _, line| vec![FontRun {
+            start: 0, end: field.lines[line].len(), bold: true, italic: false,
+        }]);
+        registry.run("move_home", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 5));
+        // So does composed text
+        editor.font_runs = Box::new(|_, _, _| Vec::new());
+        editor.field.cursor = Cursor::new(0, 0);
+        editor.field.clear_selection();
+        editor.compose("xx", 0);
+        registry.run("move_end", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 5));
+    }
+
+    #[test]
+    fn test_move_into_indented_continuation_line() {
+        let registry = CommandRegistry::builtin();
+        let mut editor = editor("  aaa bbb ccc");
+        editor.wrap = WrapMode::Viewport;
+        editor.wrap_style.hanging_indent = true;
+        editor.view_width = 7;
+        editor.field.cursor = Cursor::new(0, 3);
+        // The continuation lines start two columns in, below the first "a"
+        registry.run("move_down", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 7));
+        registry.run("move_down", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 11));
+        registry.run("move_home", &mut editor);
+        registry.run("move_up", &mut editor);
+        assert_eq!(editor.field.cursor, Cursor::new(0, 6));
+    }
+}
@@ -1,8 +1,5 @@
 //! Common functions, structs and extensions
 extern crate sdl2;
-extern crate sdl2_ttf;
-
-use sdl2_ttf::Font;
 
 
 /// A trait to more easily slice strings at character boundaries.
@@ -26,13 +23,3 @@ impl StringSliceExt for String {
         &self[i..]
     }
 }
-
-pub trait WidthOfExt {
-    fn width_of(&self, text: &str) -> u32;
-}
-impl WidthOfExt for Font {
-    fn width_of(&self, text: &str) -> u32 {
-        let (width, _) = self.size_of(text).expect("Could not get font size");
-        width
-    }
-}
\ No newline at end of file
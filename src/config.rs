@@ -0,0 +1,689 @@
+//! The user configuration file.
+extern crate sdl2;
+extern crate toml;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use sdl2::pixels::Color;
+use toml::{Table, Value};
+
+use fonts::FontOptions;
+use layout::{WrapMode, WrapStyle, ShowWhitespace, DEFAULT_WRAP_COLUMNS};
+use caret::CaretShape;
+use keymap;
+use commands;
+
+/// Colors and spacing of the text field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleConfig {
+    pub text_color: Color,
+    pub text_background: Option<Color>,
+    pub cursor_color: Color,
+    pub selection_color: Color,
+    /// The color of the markers in front of continuation lines.
+    pub wrap_marker_color: Color,
+    pub show_whitespace: ShowWhitespace,
+    /// The color whitespace is drawn in when it is shown.
+    pub whitespace_color: Color,
+    /// The band behind the line with the cursor, if it is highlighted.
+    pub current_line_color: Option<Color>,
+    /// The columns to draw rulers at, in space widths.
+    pub rulers: Vec<u32>,
+    pub ruler_color: Color,
+    /// The outline of the other occurrences of the word at the cursor, if
+    /// they are highlighted.
+    pub word_highlight: Option<Color>,
+    pub background: Option<Color>,
+    pub x_pad: u32,
+    pub y_pad: u32,
+}
+
+/// How the tab key inserts indentation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TabConfig {
+    /// The number of columns between tab stops.
+    pub width: usize,
+    /// Whether to insert spaces instead of a tab character.
+    pub expand: bool,
+}
+
+impl TabConfig {
+    /// Returns the column that text ends at, with its tabs reaching to the
+    /// next tab stop.
+    pub fn visual_col(&self, text: &str) -> usize {
+        text.chars().fold(0, |col, ch| match ch {
+            '\t' => (col / self.width + 1) * self.width,
+            _ => col + 1,
+        })
+    }
+}
+
+/// How the caret looks and blinks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaretConfig {
+    pub shape: CaretShape,
+    /// The milliseconds the caret is shown and hidden for, or 0 to not blink.
+    pub blink: u32,
+}
+
+/// The size the window opens with and the field inside it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    /// The space between the window edges and the text field.
+    pub margin: u32,
+    pub clear_color: Color,
+    pub frame_limit: u32,
+}
+
+impl WindowConfig {
+    /// Returns whether the margin leaves room for the text in a window of
+    /// the given size.
+    pub fn margin_fits(&self, width: u32, height: u32) -> bool {
+        let margins = self.margin.saturating_mul(2);
+        margins < width && margins < height
+    }
+}
+
+/// The editor configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub font: FontOptions,
+    pub style: StyleConfig,
+    pub wrap: WrapMode,
+    pub wrap_style: WrapStyle,
+    pub tabs: TabConfig,
+    pub caret: CaretConfig,
+    pub window: WindowConfig,
+    /// Changes to the default key bindings as (sequence, command) pairs.
+    pub keys: Vec<(String, String)>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            font: FontOptions::default(),
+            style: StyleConfig {
+                text_color: Color::RGBA(0, 0, 0, 255),
+                text_background: None,
+                cursor_color: Color::RGBA(255, 0, 0, 255),
+                selection_color: Color::RGBA(255, 180, 220, 255),
+                wrap_marker_color: Color::RGBA(150, 150, 170, 255),
+                show_whitespace: ShowWhitespace::None,
+                whitespace_color: Color::RGBA(170, 170, 200, 255),
+                current_line_color: None,
+                rulers: Vec::new(),
+                ruler_color: Color::RGBA(200, 200, 230, 255),
+                word_highlight: Some(Color::RGBA(130, 130, 200, 255)),
+                background: Some(Color::RGBA(220, 220, 255, 255)),
+                x_pad: 10,
+                y_pad: 10,
+            },
+            wrap: WrapMode::Width(200),
+            wrap_style: WrapStyle::default(),
+            tabs: TabConfig { width: 4, expand: true },
+            caret: CaretConfig { shape: CaretShape::default(), blink: 530 },
+            window: WindowConfig {
+                width: 800,
+                height: 600,
+                margin: 64,
+                clear_color: Color::RGBA(255, 255, 255, 255),
+                frame_limit: 30,
+            },
+            keys: Vec::new(),
+        }
+    }
+}
+
+/// Reads the values of a table, naming the full key in error messages.
+struct Section<'a> {
+    path: String,
+    table: &'a Table,
+}
+
+impl<'a> Section<'a> {
+    fn new(path: &str, table: &'a Table) -> Section<'a> {
+        Section { path: String::from(path), table: table }
+    }
+
+    /// Returns the full name of a key in this section.
+    fn key_name(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            String::from(key)
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn type_error(&self, key: &str, expected: &str, value: &Value) -> String {
+        format!("{}: expected {}, found {}", self.key_name(key), expected,
+            value.type_str())
+    }
+
+    /// Fails if the section has keys that aren't in the given list.
+    fn check_keys(&self, known: &[&str]) -> Result<(), String> {
+        for key in self.table.keys() {
+            if ! known.contains(&key.as_str()) {
+                return Err(format!("unknown key '{}' (expected one of: {})",
+                    self.key_name(key), known.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    fn section(&self, key: &str) -> Result<Option<Section<'a>>, String> {
+        match self.table.get(key) {
+            Some(&Value::Table(ref table)) => {
+                Ok(Some(Section::new(&self.key_name(key), table)))
+            },
+            Some(other) => Err(self.type_error(key, "a table", other)),
+            None => Ok(None),
+        }
+    }
+
+    fn integer(&self, key: &str, min: i64, max: i64)
+            -> Result<Option<i64>, String> {
+        match self.table.get(key) {
+            Some(&Value::Integer(value)) => {
+                if value < min || value > max {
+                    Err(format!("{}: {} is not between {} and {}",
+                        self.key_name(key), value, min, max))
+                } else {
+                    Ok(Some(value))
+                }
+            },
+            Some(other) => Err(self.type_error(key, "an integer", other)),
+            None => Ok(None),
+        }
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>, String> {
+        match self.table.get(key) {
+            Some(&Value::Boolean(value)) => Ok(Some(value)),
+            Some(other) => Err(self.type_error(key, "true or false", other)),
+            None => Ok(None),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&'a str>, String> {
+        match self.table.get(key) {
+            Some(&Value::String(ref value)) => Ok(Some(value)),
+            Some(other) => Err(self.type_error(key, "a string", other)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads an array of integers that are each between `min` and `max`.
+    fn integers(&self, key: &str, min: i64, max: i64)
+            -> Result<Option<Vec<i64>>, String> {
+        let values = match self.table.get(key) {
+            Some(&Value::Array(ref values)) => values,
+            Some(other) => {
+                return Err(self.type_error(key, "an array of integers", other));
+            },
+            None => return Ok(None),
+        };
+        let mut integers = Vec::new();
+        for value in values {
+            match *value {
+                Value::Integer(value) if value < min || value > max => {
+                    return Err(format!("{}: {} is not between {} and {}",
+                        self.key_name(key), value, min, max));
+                },
+                Value::Integer(value) => integers.push(value),
+                ref other => {
+                    return Err(self.type_error(key, "an array of integers", 
+                        other));
+                },
+            }
+        }
+        Ok(Some(integers))
+    }
+
+    /// Reads a color written as "#rrggbb" or "#rrggbbaa".
+    fn color(&self, key: &str) -> Result<Option<Color>, String> {
+        let text = match self.string(key)? {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        parse_color(text).map(Some).ok_or_else(|| {
+            format!("{}: '{}' is not a color like \"#rrggbb\" or \"#rrggbbaa\"",
+                self.key_name(key), text)
+        })
+    }
+
+    /// Reads a color that can also be turned off with `false`.
+    fn optional_color(&self, key: &str) -> Result<Option<Option<Color>>, String> {
+        match self.table.get(key) {
+            Some(&Value::Boolean(false)) => Ok(Some(None)),
+            Some(&Value::String(_)) => self.color(key).map(|c| c.map(Some)),
+            Some(other) => Err(self.type_error(key, "a color or false", other)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Parses a color written as "#rrggbb" or "#rrggbbaa".
+pub fn parse_color(text: &str) -> Option<Color> {
+    if ! text.is_ascii() || ! text.starts_with('#') || 
+            ! (text.len() == 7 || text.len() == 9) {
+        return None;
+    }
+    let mut parts = Vec::new();
+    for i in 0 .. (text.len() - 1) / 2 {
+        match u8::from_str_radix(&text[1 + i * 2 .. 3 + i * 2], 16) {
+            Ok(value) => parts.push(value),
+            Err(_) => return None,
+        }
+    }
+    let alpha = if parts.len() == 4 { parts[3] } else { 255 };
+    Some(Color::RGBA(parts[0], parts[1], parts[2], alpha))
+}
+
+impl Config {
+    /// Parses a configuration, using the defaults for missing values.
+    pub fn parse(source: &str) -> Result<Config, String> {
+        let table = source.parse::<Table>().map_err(|e| e.to_string())?;
+        let root = Section::new("", &table);
+        root.check_keys(&["font", "style", "wrap", "tabs", "caret",
+            "window", "keys"])?;
+        let mut config = Config::default();
+
+        if let Some(font) = root.section("font")? {
+            font.check_keys(&["family", "size"])?;
+            if let Some(family) = font.string("family")? {
+                config.font.family = Some(String::from(family));
+            }
+            if let Some(size) = font.integer("size", 1, 512)? {
+                config.font.size = size as u16;
+            }
+        }
+
+        if let Some(style) = root.section("style")? {
+            style.check_keys(&["text", "text_background", "cursor",
+                "selection", "wrap_marker", "whitespace", "whitespace_color",
+                "current_line_color", "rulers", "ruler_color", 
+                "word_highlight", "background", "x_pad", "y_pad"])?;
+            let ref mut s = config.style;
+            if let Some(color) = style.color("text")? {
+                s.text_color = color;
+            }
+            if let Some(color) = style.optional_color("text_background")? {
+                s.text_background = color;
+            }
+            if let Some(color) = style.color("cursor")? {
+                s.cursor_color = color;
+            }
+            if let Some(color) = style.color("selection")? {
+                s.selection_color = color;
+            }
+            if let Some(color) = style.color("wrap_marker")? {
+                s.wrap_marker_color = color;
+            }
+            s.show_whitespace = match style.string("whitespace")? {
+                Some("none") => ShowWhitespace::None,
+                Some("always") => ShowWhitespace::Always,
+                Some("selection") => ShowWhitespace::Selection,
+                Some("trailing") => ShowWhitespace::Trailing,
+                None => s.show_whitespace,
+                Some(other) => {
+                    return Err(format!("style.whitespace: unknown value '{}' \
+                        (expected \"none\", \"always\", \"selection\" or \
+                        \"trailing\")", other));
+                },
+            };
+            if let Some(color) = style.color("whitespace_color")? {
+                s.whitespace_color = color;
+            }
+            let current_line = style.optional_color("current_line_color")?;
+            if let Some(color) = current_line {
+                s.current_line_color = color;
+            }
+            if let Some(rulers) = style.integers("rulers", 1, 10000)? {
+                s.rulers = rulers.into_iter().map(|col| col as u32).collect();
+            }
+            if let Some(color) = style.color("ruler_color")? {
+                s.ruler_color = color;
+            }
+            if let Some(color) = style.optional_color("word_highlight")? {
+                s.word_highlight = color;
+            }
+            if let Some(color) = style.optional_color("background")? {
+                s.background = color;
+            }
+            if let Some(pad) = style.integer("x_pad", 0, 1000)? {
+                s.x_pad = pad as u32;
+            }
+            if let Some(pad) = style.integer("y_pad", 0, 1000)? {
+                s.y_pad = pad as u32;
+            }
+        }
+
+        if let Some(wrap) = root.section("wrap")? {
+            wrap.check_keys(&["mode", "width", "columns", "indent", 
+                "extra_indent", "markers"])?;
+            let ref mut w = config.wrap_style;
+            if let Some(indent) = wrap.boolean("indent")? {
+                w.hanging_indent = indent;
+            }
+            if let Some(extra) = wrap.integer("extra_indent", 0, 100)? {
+                w.extra_indent = extra as u32;
+            }
+            if let Some(markers) = wrap.boolean("markers")? {
+                w.markers = markers;
+            }
+            let width = wrap.integer("width", 1, 100000)?;
+            let columns = wrap.integer("columns", 1, 10000)?;
+            config.wrap = match wrap.string("mode")? {
+                Some("none") => WrapMode::None,
+                Some("viewport") => WrapMode::Viewport,
+                Some("character") => WrapMode::Character,
+                Some("columns") => WrapMode::Columns(
+                    columns.map_or(DEFAULT_WRAP_COLUMNS, |c| c as u32)),
+                Some("width") | None => {
+                    let default = match config.wrap {
+                        WrapMode::Width(width) => width,
+                        _ => 200,
+                    };
+                    WrapMode::Width(width.map_or(default, |w| w as u32))
+                },
+                Some(other) => {
+                    return Err(format!("wrap.mode: unknown mode '{}' \
+                        (expected \"none\", \"width\", \"viewport\", \
+                        \"columns\" or \"character\")", other));
+                },
+            };
+        }
+
+        if let Some(tabs) = root.section("tabs")? {
+            tabs.check_keys(&["width", "expand"])?;
+            if let Some(width) = tabs.integer("width", 1, 16)? {
+                config.tabs.width = width as usize;
+            }
+            if let Some(expand) = tabs.boolean("expand")? {
+                config.tabs.expand = expand;
+            }
+        }
+
+        if let Some(caret) = root.section("caret")? {
+            caret.check_keys(&["shape", "width", "blink"])?;
+            let width = caret.integer("width", 1, 100)?;
+            config.caret.shape = match caret.string("shape")? {
+                Some("beam") => CaretShape::Beam(width.map_or(1, |w| w as u32)),
+                Some("block") => CaretShape::Block,
+                Some("underline") => CaretShape::Underline,
+                None => match (config.caret.shape, width) {
+                    (CaretShape::Beam(_), Some(width)) => {
+                        CaretShape::Beam(width as u32)
+                    },
+                    (shape, _) => shape,
+                },
+                Some(other) => {
+                    return Err(format!("caret.shape: unknown shape '{}' \
+                        (expected \"beam\", \"block\" or \"underline\")", 
+                        other));
+                },
+            };
+            if let Some(blink) = caret.integer("blink", 0, 10000)? {
+                config.caret.blink = blink as u32;
+            }
+        }
+
+        if let Some(window) = root.section("window")? {
+            window.check_keys(&["width", "height", "margin",
+                "clear_color", "frame_limit"])?;
+            let ref mut w = config.window;
+            if let Some(width) = window.integer("width", 100, 16384)? {
+                w.width = width as u32;
+            }
+            if let Some(height) = window.integer("height", 100, 16384)? {
+                w.height = height as u32;
+            }
+            if let Some(margin) = window.integer("margin", 0, 1000)? {
+                w.margin = margin as u32;
+            }
+            if let Some(color) = window.color("clear_color")? {
+                w.clear_color = color;
+            }
+            if let Some(limit) = window.integer("frame_limit", 1, 1000)? {
+                w.frame_limit = limit as u32;
+            }
+            if ! w.margin_fits(w.width, w.height) {
+                return Err(String::from(
+                    "window.margin: the margin leaves no room for the text"));
+            }
+        }
+
+        if let Some(keys) = root.section("keys")? {
+            for sequence in keys.table.keys() {
+                let name = format!("keys.\"{}\"", sequence);
+                keymap::parse_sequence(sequence)
+                    .map_err(|e| format!("{}: {}", name, e))?;
+                let command = keys.string(sequence)?.unwrap();
+                if command != "none" && ! commands::is_builtin(command) {
+                    return Err(format!("{}: unknown command '{}'", name, 
+                        command));
+                }
+                config.keys.push((sequence.clone(), String::from(command)));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Loads the configuration file at the given path.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let mut source = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Config::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// Returns the default path of the configuration file, in the XDG config
+/// directory.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if ! dir.is_empty() => PathBuf::from(dir),
+        _ => match env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(".config"),
+            None => return None,
+        },
+    };
+    Some(dir.join("editor").join("config.toml"))
+}
+
+/// Settings given on the command line, which take precedence over the file.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    pub font_family: Option<String>,
+    pub font_size: Option<u16>,
+}
+
+impl Overrides {
+    /// Replaces the settings of the config that are overridden.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(ref family) = self.font_family {
+            config.font.family = Some(family.clone());
+        }
+        if let Some(size) = self.font_size {
+            config.font.size = size;
+        }
+    }
+}
+
+/// Watches a configuration file and reloads it when it changes.
+pub struct ConfigWatcher {
+    pub path: PathBuf,
+    pub overrides: Overrides,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching the file at the given path.
+    pub fn new(path: PathBuf, overrides: Overrides) -> ConfigWatcher {
+        let modified = modified_time(&path);
+        ConfigWatcher { path: path, overrides: overrides, modified: modified }
+    }
+
+    /// Loads the configuration, or the defaults if the file doesn't exist.
+    pub fn load(&self) -> Result<Config, String> {
+        let mut config = if self.path.exists() {
+            Config::load(&self.path)?
+        } else {
+            Config::default()
+        };
+        self.overrides.apply(&mut config);
+        Ok(config)
+    }
+
+    /// Returns the reloaded configuration if the file changed since it was
+    /// last checked.
+    pub fn poll(&mut self) -> Option<Result<Config, String>> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(self.load())
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::pixels::Color;
+    use layout::{WrapMode, ShowWhitespace};
+    use caret::CaretShape;
+
+    #[test]
+    fn test_empty_config_is_default() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let config = Config::parse("
+            [font]
+            family = \"DejaVu Sans Mono\"
+            size = 12
+
+            [style]
+            cursor = \"#00ff0080\"
+            background = false
+
+            [wrap]
+            mode = \"none\"
+        ").unwrap();
+        assert_eq!(config.font.family, Some(String::from("DejaVu Sans Mono")));
+        assert_eq!(config.font.size, 12);
+        assert_eq!(config.style.cursor_color, Color::RGBA(0, 255, 0, 128));
+        assert_eq!(config.style.background, None);
+        assert_eq!(config.wrap, WrapMode::None);
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        let config = Config::parse("[wrap]\nmode = \"viewport\"").unwrap();
+        assert_eq!(config.wrap, WrapMode::Viewport);
+        let config = Config::parse("[wrap]\nwidth = 300").unwrap();
+        assert_eq!(config.wrap, WrapMode::Width(300));
+        let config = Config::parse("[wrap]\nmode = \"columns\"\ncolumns = 72")
+            .unwrap();
+        assert_eq!(config.wrap, WrapMode::Columns(72));
+        let config = Config::parse("[wrap]\nindent = true\nextra_indent = 2")
+            .unwrap();
+        assert!(config.wrap_style.hanging_indent && ! config.wrap_style.markers);
+        assert_eq!(config.wrap_style.extra_indent, 2);
+    }
+
+    #[test]
+    fn test_show_whitespace() {
+        let config = Config::parse("[style]\nwhitespace = \"trailing\"")
+            .unwrap();
+        assert_eq!(config.style.show_whitespace, ShowWhitespace::Trailing);
+        let err = Config::parse("[style]\nwhitespace = \"some\"").unwrap_err();
+        assert!(err.starts_with("style.whitespace: unknown value 'some'"));
+    }
+
+    #[test]
+    fn test_current_line_color_and_rulers() {
+        let config = Config::parse("[style]\n\
+            current_line_color = \"#eeeeee\"\nrulers = [80, 100]").unwrap();
+        assert_eq!(config.style.current_line_color, 
+            Some(Color::RGBA(238, 238, 238, 255)));
+        assert_eq!(config.style.rulers, vec![80, 100]);
+        let err = Config::parse("[style]\nrulers = [80, \"a\"]").unwrap_err();
+        assert_eq!(err, "style.rulers: expected an array of integers, \
+            found string");
+        let err = Config::parse("[style]\nrulers = [0]").unwrap_err();
+        assert_eq!(err, "style.rulers: 0 is not between 1 and 10000");
+    }
+
+    #[test]
+    fn test_caret() {
+        let config = Config::parse("[caret]\nwidth = 3\nblink = 0").unwrap();
+        assert_eq!(config.caret.shape, CaretShape::Beam(3));
+        assert_eq!(config.caret.blink, 0);
+        let config = Config::parse("[caret]\nshape = \"block\"").unwrap();
+        assert_eq!(config.caret.shape, CaretShape::Block);
+        let err = Config::parse("[caret]\nshape = \"box\"").unwrap_err();
+        assert!(err.starts_with("caret.shape: unknown shape 'box'"));
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let err = Config::parse("[style]\npadding = 3").unwrap_err();
+        assert!(err.starts_with("unknown key 'style.padding'"), "{}", err);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let err = Config::parse("[tabs]\nwidth = \"4\"").unwrap_err();
+        assert_eq!(err, "tabs.width: expected an integer, found string");
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let err = Config::parse("[font]\nsize = 0").unwrap_err();
+        assert_eq!(err, "font.size: 0 is not between 1 and 512");
+    }
+
+    #[test]
+    fn test_margin_fits() {
+        let err = Config::parse("[window]\nwidth = 200\nmargin = 100")
+            .unwrap_err();
+        assert!(err.starts_with("window.margin"));
+        let mut window = Config::default().window;
+        window.margin = u32::max_value();
+        assert!(! window.margin_fits(800, 600));
+        window.margin = 64;
+        assert!(window.margin_fits(800, 600));
+        assert!(! window.margin_fits(128, 600));
+    }
+
+    #[test]
+    fn test_keys() {
+        let config = Config::parse("[keys]\n\"Ctrl+K Ctrl+C\" = \"copy\"")
+            .unwrap();
+        assert_eq!(config.keys, vec![
+            (String::from("Ctrl+K Ctrl+C"), String::from("copy"))
+        ]);
+        let err = Config::parse("[keys]\n\"Ctrl+K\" = \"fly\"").unwrap_err();
+        assert_eq!(err, "keys.\"Ctrl+K\": unknown command 'fly'");
+    }
+
+    #[test]
+    fn test_bad_color() {
+        let err = Config::parse("[style]\ntext = \"red\"").unwrap_err();
+        assert!(err.starts_with("style.text: 'red' is not a color"), "{}", err);
+    }
+}
@@ -0,0 +1,109 @@
+//! Tracking which lines of a text field need to be drawn again.
+
+use std::collections::BTreeSet;
+use cursor::Cursor;
+
+/// The lines of a text field that changed since it was last drawn.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Damage {
+    /// Everything has to be drawn, including the area around the lines.
+    all: bool,
+    /// Every line from this one and the space below the last line changed,
+    /// like after an edit that adds or removes lines.
+    from: Option<usize>,
+    lines: BTreeSet<usize>,
+}
+
+impl Damage {
+    /// Creates damage that covers nothing.
+    pub fn new() -> Damage {
+        Damage::default()
+    }
+
+    /// Marks everything as damaged.
+    pub fn all(&mut self) {
+        self.all = true;
+    }
+
+    /// Marks a single line as damaged.
+    pub fn line(&mut self, line: usize) {
+        self.lines.insert(line);
+    }
+
+    /// Marks the lines in a range (inclusive) as damaged.
+    pub fn lines(&mut self, first: usize, last: usize) {
+        if self.from.map_or(false, |from| from <= first) {
+            return;
+        }
+        for line in first .. last + 1 {
+            self.lines.insert(line);
+        }
+    }
+
+    /// Marks a line, the lines after it and the space below them as damaged.
+    pub fn lines_from(&mut self, line: usize) {
+        self.from = Some(match self.from {
+            Some(from) if from < line => from,
+            _ => line,
+        });
+    }
+
+    /// Marks the lines that a cursor and selection marker cover as damaged.
+    pub fn selection(&mut self, cursor: Cursor, marker: Cursor) {
+        let (first, last) = cursor.order(&marker);
+        self.lines(first.line, last.line);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        ! self.all && self.from.is_none() && self.lines.is_empty()
+    }
+
+    /// Returns whether everything is damaged.
+    pub fn is_all(&self) -> bool {
+        self.all
+    }
+
+    /// Returns whether a line needs to be drawn.
+    pub fn contains(&self, line: usize) -> bool {
+        self.all || self.from.map_or(false, |from| from <= line)
+            || self.lines.contains(&line)
+    }
+
+    /// Returns whether the space below the last line needs to be drawn.
+    pub fn contains_end(&self) -> bool {
+        self.all || self.from.is_some()
+    }
+
+    /// Returns the damage and resets it to nothing.
+    pub fn take(&mut self) -> Damage {
+        ::std::mem::replace(self, Damage::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::Cursor;
+
+    #[test]
+    fn test_lines() {
+        let mut damage = Damage::new();
+        assert!(damage.is_empty());
+        damage.selection(Cursor::new(4, 0), Cursor::new(2, 3));
+        assert!(! damage.contains(1) && damage.contains(3) && ! damage.contains(5));
+        assert!(! damage.contains_end());
+    }
+
+    #[test]
+    fn test_lines_from() {
+        let mut damage = Damage::new();
+        damage.lines_from(5);
+        damage.lines_from(7);
+        damage.line(1);
+        assert!(damage.contains(1) && ! damage.contains(4));
+        assert!(damage.contains(5) && damage.contains(100));
+        assert!(damage.contains_end());
+        let taken = damage.take();
+        assert!(damage.is_empty() && ! taken.is_empty());
+    }
+}
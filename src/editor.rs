@@ -0,0 +1,241 @@
+//! The editor state around a text field.
+
+use std::cmp;
+use common::StringSliceExt;
+use cursor::Cursor;
+use textfield::{LineLayout, Textfield};
+use config::TabConfig;
+use highlight::Highlighter;
+use layout::{WrapMode, WrapStyle, average_char_width};
+use measure::{FontRun, LineMeasurer, TextMeasurer, FixedWidthMeasurer};
+use clipboard::{Clipboard, MemoryClipboard};
+use registers::{KillRing, Registers};
+
+/// Text being composed with an input method, which is shown at the caret
+/// but not part of the text until the input method commits it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composition {
+    pub text: String,
+    /// The caret position within the composed text, in characters.
+    pub cursor: usize,
+}
+
+/// Returns where a line wraps at a width, if it wraps, and how far its
+/// continuation lines are indented.
+fn line_breaks<M: TextMeasurer>(line: &str, wrap: WrapMode, style: WrapStyle,
+        width: Option<u32>, measurer: &M) -> (Vec<usize>, u32) {
+    match width {
+        Some(width) => {
+            let char_width = average_char_width(&measurer);
+            let indent = style.continuation_indent(line, &measurer, width,
+                char_width);
+            (wrap.wrap_indented(line, &measurer, width, indent), indent)
+        },
+        None => (Vec::new(), 0),
+    }
+}
+
+/// Returns the runs of a line of a text field that the view draws with bold
+/// or italic fonts.
+pub type FontRunsFn = dyn Fn(&Textfield, Option<&Highlighter>, usize) 
+    -> Vec<FontRun>;
+
+/// Lays out lines like the view draws them: with the fonts of their styles,
+/// the composition at the cursor, and wrapped at the width of the view.
+struct ViewLayout<'a> {
+    measurer: &'a dyn TextMeasurer,
+    /// The font runs of the lines that a move can reach.
+    runs: Vec<(usize, Vec<FontRun>)>,
+    /// The line and byte index of the composition, and its width.
+    composition: Option<(usize, usize, u32)>,
+    wrap: WrapMode,
+    style: WrapStyle,
+    width: Option<u32>,
+}
+
+impl<'a> LineLayout for ViewLayout<'a> {
+    fn lay_out<T, F>(&self, lineno: usize, line: &str, f: F) -> T
+            where F: FnOnce(&dyn TextMeasurer, &[usize], u32) -> T {
+        let runs = self.runs.iter().find(|&&(runs_line, _)| runs_line == lineno)
+            .map_or(&[][..], |&(_, ref runs)| &runs[..]);
+        let inserted = match self.composition {
+            Some((line, at, width)) if line == lineno => Some((at, width)),
+            _ => None,
+        };
+        let measurer = LineMeasurer {
+            line: line, runs: runs, measurer: self.measurer, 
+            inserted: inserted, max_advance: None,
+        };
+        let (breaks, indent) = line_breaks(line, self.wrap, self.style, 
+            self.width, &measurer);
+        f(&measurer, &breaks, indent)
+    }
+}
+
+/// A text field and the state that editing commands act on besides it.
+pub struct Editor {
+    pub field: Textfield,
+    /// The clipboard used by cut, copy and paste.
+    pub clipboard: Box<dyn Clipboard>,
+    /// The primary selection, on systems that have one. It follows the
+    /// selected text and is pasted with the middle mouse button.
+    pub primary: Option<Box<dyn Clipboard>>,
+    pub kill_ring: KillRing,
+    pub registers: Registers,
+    /// The register chosen for the next command, if any.
+    pub register: Option<char>,
+    /// Whether the next text input names a register instead of being
+    /// inserted.
+    pub awaiting_register: bool,
+    /// The name of the last command run, so that commands can tell whether
+    /// they follow themselves. Text input resets it.
+    pub last_command: Option<&'static str>,
+    /// Where the text of the last yank was inserted.
+    pub last_yank: Option<(Cursor, Cursor)>,
+    /// The input method composition in progress, if any.
+    pub composition: Option<Composition>,
+    pub tabs: TabConfig,
+    /// How lines are wrapped in the view.
+    pub wrap: WrapMode,
+    /// How the continuation lines of wrapped lines are indented and marked.
+    pub wrap_style: WrapStyle,
+    /// Measures text in the fonts of the view, for moving by visual lines.
+    pub measurer: Box<dyn TextMeasurer>,
+    /// The font runs of lines, so that they are measured like the view draws
+    /// them.
+    pub font_runs: Box<FontRunsFn>,
+    /// Highlights the text, if its language is known.
+    pub highlighter: Option<Highlighter>,
+    /// The width of the text in the view, which lines wrap at when wrapping
+    /// at the viewport.
+    pub view_width: u32,
+    /// The x coordinate that moving up and down aims for, kept while moving
+    /// so that short lines in between don't pull the cursor to the left.
+    pub goal_x: Option<i32>,
+    /// Set by the quit command to end the main loop.
+    pub quit: bool,
+}
+
+impl Editor {
+    /// Creates an editor for the given text field, with an in-memory
+    /// clipboard and no primary selection.
+    pub fn new(field: Textfield, tabs: TabConfig) -> Editor {
+        Editor { 
+            field: field, clipboard: Box::new(MemoryClipboard::new()),
+            primary: None, kill_ring: KillRing::default(),
+            registers: Registers::new(), register: None,
+            awaiting_register: false, last_command: None, last_yank: None,
+            composition: None, tabs: tabs, wrap: WrapMode::None, 
+            wrap_style: WrapStyle::default(),
+            measurer: Box::new(FixedWidthMeasurer::new(1, 1)), 
+            font_runs: Box::new(|_, _, _| Vec::new()), highlighter: None, 
+            view_width: 1, goal_x: None, quit: false,
+        }
+    }
+
+    /// Returns the width lines wrap at in the view, if they wrap.
+    pub fn wrap_width(&self) -> Option<u32> {
+        let measurer = &*self.measurer;
+        self.wrap.width(self.view_width, average_char_width(&measurer))
+    }
+
+    /// Moves the cursor, or the selection marker when selecting, a visual
+    /// line up or down. Moves right after another one keep aiming for the
+    /// same x coordinate.
+    pub fn move_vertically(&mut self, down: bool, select: bool) {
+        let goal_x = match self.last_command {
+            Some("move_up") | Some("move_down") | Some("select_up") 
+                | Some("select_down") => self.goal_x,
+            _ => None,
+        };
+        let layout = self.view_layout(&*self.measurer);
+        self.goal_x = Some(self.field.move_visual(down, select, goal_x, 
+            &layout));
+    }
+
+    /// Moves the cursor, or the selection marker when selecting, to the
+    /// start or end of its visual line.
+    pub fn move_to_line_edge(&mut self, to_end: bool, select: bool) {
+        let layout = self.view_layout(&*self.measurer);
+        self.field.move_visual_edge(to_end, select, &layout);
+    }
+
+    /// Returns how the view lays out the lines that moving the cursor or the
+    /// selection marker can reach, measured with the editor's measurer.
+    fn view_layout<'a>(&self, measurer: &'a dyn TextMeasurer) 
+            -> ViewLayout<'a> {
+        let field = &self.field;
+        let (cursor, marker) = (field.cons_cursor(), field.cons_marker());
+        let mut lines: Vec<usize> = [cursor.line, marker.line].iter()
+            .flat_map(|&line| line.saturating_sub(1) .. line + 2)
+            .filter(|&line| line < field.lines.len())
+            .collect();
+        lines.sort();
+        lines.dedup();
+        let runs = lines.into_iter().map(|line| {
+            (line, (self.font_runs)(field, self.highlighter.as_ref(), line))
+        }).collect();
+        let composition = self.composition.as_ref().map(|composition| {
+            let line = &field.lines[cursor.line];
+            let at = line.len() - line.slice_after(cursor.col).len();
+            (cursor.line, at, measurer.width(&composition.text))
+        });
+        ViewLayout {
+            measurer: measurer, runs: runs, composition: composition,
+            wrap: self.wrap, style: self.wrap_style, width: self.wrap_width(),
+        }
+    }
+
+    /// Records that a command was run. The chosen register only lasts for
+    /// the command after choosing it.
+    pub fn finish_command(&mut self, name: &'static str) {
+        if ! self.awaiting_register {
+            self.register = None;
+        }
+        self.last_command = Some(name);
+    }
+
+    /// Updates the input method composition. An empty text ends it.
+    pub fn compose(&mut self, text: &str, cursor: usize) {
+        self.composition = if text.is_empty() {
+            None
+        } else {
+            let cursor = cmp::min(cursor, text.chars().count());
+            Some(Composition { text: String::from(text), cursor: cursor })
+        };
+    }
+
+    /// Handles typed text, which names a register after choosing one and is
+    /// inserted otherwise.
+    pub fn input_text(&mut self, text: &str) {
+        // Committed text replaces the composition
+        self.composition = None;
+        if self.awaiting_register {
+            self.awaiting_register = false;
+            match text.chars().next() {
+                Some(name) if Registers::is_valid(name) => {
+                    self.register = Some(name);
+                },
+                _ => println!("Not a register: {:?}", text),
+            }
+            return;
+        }
+        self.field.insert(text);
+        self.last_command = None;
+    }
+
+    /// Puts the selected text in the primary selection, if there is any.
+    pub fn update_primary(&mut self) {
+        if let Some(ref mut primary) = self.primary {
+            if self.field.has_selection() {
+                primary.set_text(&self.field.selected_text());
+            }
+        }
+    }
+
+    /// Sets the selected text that the primary selection held back. Returns
+    /// whether it is still waiting.
+    pub fn flush_primary(&mut self) -> bool {
+        self.primary.as_mut().map_or(false, |primary| primary.flush())
+    }
+}
@@ -0,0 +1,149 @@
+//! Finding and loading the fonts used by the editor.
+extern crate sdl2;
+extern crate sdl2_ttf;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use sdl2::rwops::RWops;
+use sdl2_ttf::{Font, FontStyle, Sdl2TtfContext};
+
+/// A small open-license monospace font that is always available.
+const BUNDLED_FONT: &'static [u8] = include_bytes!("../fonts/DejaVuSansMono.ttf");
+
+pub const DEFAULT_FONT_SIZE: u16 = 16;
+
+/// Fonts with wide character coverage to try for glyphs the main font lacks.
+const FALLBACK_FONTS: [&'static str; 6] = [
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/System/Library/Fonts/Apple Symbols.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/noto/NotoSansSymbols2-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoEmoji-Regular.ttf",
+];
+
+/// Which font to use and at what size.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FontOptions {
+    /// A font family name or the path of a font file. The bundled font is
+    /// used when this is `None` or the font can't be found.
+    pub family: Option<String>,
+    pub size: u16,
+}
+
+impl Default for FontOptions {
+    fn default() -> FontOptions {
+        FontOptions { family: None, size: DEFAULT_FONT_SIZE }
+    }
+}
+
+/// Asks fontconfig for the file of the best match for a font pattern.
+fn fontconfig_match(pattern: &str) -> Option<PathBuf> {
+    let output = match Command::new("fc-match")
+            .arg("--format=%{file}").arg(pattern).output() {
+        Ok(output) => output,
+        Err(_) => return None,
+    };
+    let file = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && ! file.is_empty() {
+        Some(PathBuf::from(file))
+    } else {
+        None
+    }
+}
+
+/// Looks for a font file named after the family in the macOS font folders.
+fn font_dir_match(family: &str) -> Option<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/Library/Fonts"), PathBuf::from("/System/Library/Fonts"),
+    ];
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(Path::new(&home).join("Library/Fonts"));
+    }
+    for dir in dirs {
+        for ext in &["ttf", "ttc", "otf"] {
+            let path = dir.join(format!("{}.{}", family, ext));
+            if path.exists() {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+/// Finds the file of a font given by path or by family name.
+pub fn find_font(family: &str, bold: bool, italic: bool) -> Option<PathBuf> {
+    if Path::new(family).is_file() {
+        return Some(PathBuf::from(family));
+    }
+    if cfg!(target_os = "linux") {
+        // fontconfig happily substitutes another family, so check the name
+        let mut pattern = String::from(family);
+        if bold {
+            pattern.push_str(":bold");
+        }
+        if italic {
+            pattern.push_str(":italic");
+        }
+        let matched = fontconfig_match(&pattern);
+        let found = Command::new("fc-list").arg(family).output()
+            .map(|out| ! out.stdout.is_empty()).unwrap_or(false);
+        if found { matched } else { None }
+    } else {
+        font_dir_match(family)
+    }
+}
+
+/// Loads the font given by the options with the given style. Styles the font
+/// has no file for are synthesized, and the bundled font is used when the
+/// font can't be found or loaded.
+pub fn load_font(ttf: &Sdl2TtfContext, options: &FontOptions, style: FontStyle)
+        -> Font {
+    let bold = style.contains(sdl2_ttf::STYLE_BOLD);
+    let italic = style.contains(sdl2_ttf::STYLE_ITALIC);
+    let plain = style.is_empty();
+    let loaded = options.family.as_ref().and_then(|family| {
+        let regular = find_font(family, false, false);
+        let styled = if plain { regular.clone() } else {
+            find_font(family, bold, italic)
+        };
+        styled.and_then(|path| {
+            match ttf.load_font(&path, options.size) {
+                Ok(mut font) => {
+                    if ! plain && Some(&path) == regular.as_ref() {
+                        font.set_style(style);
+                    }
+                    Some(font)
+                },
+                Err(e) => {
+                    println!("Could not load font {}: {}", path.display(), e);
+                    None
+                },
+            }
+        })
+    });
+    if loaded.is_none() && plain {
+        if let Some(ref family) = options.family {
+            println!("Font '{}' not found, using the bundled font", family);
+        }
+    }
+    loaded.unwrap_or_else(|| {
+        let rwops = RWops::from_bytes(BUNDLED_FONT)
+            .expect("Could not read the bundled font");
+        let mut font = ttf.load_font_from_rwops(rwops, options.size)
+            .expect("Could not load the bundled font");
+        font.set_style(style);
+        font
+    })
+}
+
+/// Loads the fallback fonts that exist on this system.
+pub fn load_fallbacks(ttf: &Sdl2TtfContext, size: u16) -> Vec<Rc<Font>> {
+    FALLBACK_FONTS.iter()
+        .filter(|path| Path::new(path).exists())
+        .filter_map(|path| ttf.load_font(Path::new(path), size).ok())
+        .map(Rc::new)
+        .collect()
+}
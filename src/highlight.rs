@@ -0,0 +1,502 @@
+//! Syntax highlighting of text into styled spans.
+extern crate sdl2;
+extern crate regex;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use sdl2::pixels::Color;
+use regex::Regex;
+
+use textfield::LineEdit;
+
+const BUILTIN_GRAMMARS: [&'static str; 3] = [
+    include_str!("../grammars/rust.grammar"),
+    include_str!("../grammars/toml.grammar"),
+    include_str!("../grammars/markdown.grammar"),
+];
+
+/// A rule of a grammar.
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// Text matching the pattern on a single line.
+    Match { scope: String, pattern: Regex },
+    /// Text between a start and an end pattern, possibly spanning lines.
+    Region { scope: String, start: Regex, end: Regex },
+}
+
+impl Rule {
+    /// Returns the scope of the text this rule matches.
+    pub fn scope(&self) -> &str {
+        match *self {
+            Rule::Match { ref scope, .. } | Rule::Region { ref scope, .. } => scope,
+        }
+    }
+}
+
+/// A set of rules for tokenizing a language.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub rules: Vec<Rule>,
+}
+
+impl Grammar {
+    /// Parses a grammar definition.
+    ///
+    /// Each line is either a comment starting with '#', `name <name>`,
+    /// `extensions <ext>...`, `match <scope> <pattern>` or
+    /// `region <scope> <start> <end>`. Patterns are separated by whitespace.
+    pub fn parse(source: &str) -> Result<Grammar, String> {
+        let mut grammar = Grammar {
+            name: String::new(), extensions: Vec::new(), rules: Vec::new(),
+        };
+        for (lineno, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<_> = line.split_whitespace().collect();
+            let error = |msg: &str| format!("line {}: {}", lineno + 1, msg);
+            let compile = |pattern: &str| Regex::new(pattern)
+                .map_err(|e| error(&format!("invalid pattern: {}", e)));
+            match (words[0], words.len()) {
+                ("name", 2) => {
+                    grammar.name = words[1].to_string();
+                },
+                ("extensions", _) => {
+                    grammar.extensions.extend(
+                        words[1..].iter().map(|s| s.to_string()));
+                },
+                ("match", 3) => {
+                    grammar.rules.push(Rule::Match {
+                        scope: words[1].to_string(),
+                        pattern: compile(words[2])?,
+                    });
+                },
+                ("region", 4) => {
+                    grammar.rules.push(Rule::Region {
+                        scope: words[1].to_string(),
+                        start: compile(words[2])?,
+                        end: compile(words[3])?,
+                    });
+                },
+                (other, _) => {
+                    return Err(error(&format!("malformed '{}' rule", other)));
+                },
+            }
+        }
+        if grammar.name.is_empty() {
+            return Err(String::from("grammar has no name"));
+        }
+        Ok(grammar)
+    }
+
+    /// Loads a grammar definition from the given file.
+    pub fn load(path: &Path) -> Result<Grammar, String> {
+        let mut source = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut source))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Grammar::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Tokenizes a line starting in the given state. Returns spans covering
+    /// the whole line and the state at the end of it.
+    pub fn tokenize_line(&self, line: &str, state: LineState)
+            -> (Vec<Span>, LineState) {
+        let mut spans = Vec::new();
+        let mut pos = 0;
+
+        // Finish a region left open by a previous line
+        if let Some(index) = state {
+            if let Rule::Region { ref end, .. } = self.rules[index] {
+                match end.find_at(line, 0) {
+                    Some(m) => {
+                        push_span(&mut spans, 0, m.end(), Some(index));
+                        pos = m.end();
+                    },
+                    None => {
+                        push_span(&mut spans, 0, line.len(), Some(index));
+                        return (spans, state);
+                    },
+                }
+            }
+        }
+
+        while pos < line.len() {
+            // Earliest non-empty match, with earlier rules winning ties
+            let mut best: Option<(usize, usize, usize)> = None;
+            for (index, rule) in self.rules.iter().enumerate() {
+                let pattern = match *rule {
+                    Rule::Match { ref pattern, .. } => pattern,
+                    Rule::Region { ref start, .. } => start,
+                };
+                if let Some(m) = pattern.find_at(line, pos) {
+                    if m.start() == m.end() {
+                        continue;
+                    }
+                    let better = match best {
+                        Some((start, _, _)) => m.start() < start,
+                        None => true,
+                    };
+                    if better {
+                        best = Some((m.start(), m.end(), index));
+                    }
+                }
+            }
+
+            let (start, end, index) = match best {
+                Some(found) => found,
+                None => break,
+            };
+            push_span(&mut spans, pos, start, None);
+            if let Rule::Region { end: ref end_pattern, .. } = self.rules[index] {
+                match end_pattern.find_at(line, end) {
+                    Some(m) => {
+                        push_span(&mut spans, start, m.end(), Some(index));
+                        pos = m.end();
+                    },
+                    None => {
+                        push_span(&mut spans, start, line.len(), Some(index));
+                        return (spans, Some(index));
+                    },
+                }
+            } else {
+                push_span(&mut spans, start, end, Some(index));
+                pos = end;
+            }
+        }
+        push_span(&mut spans, pos, line.len(), None);
+        (spans, None)
+    }
+}
+
+/// Adds a span to the list, merging it with the previous one if they share
+/// the same rule.
+fn push_span(spans: &mut Vec<Span>, start: usize, end: usize,
+        rule: Option<usize>) {
+    if start == end {
+        return;
+    }
+    if let Some(last) = spans.last_mut() {
+        if last.rule == rule && last.end == start {
+            last.end = end;
+            return;
+        }
+    }
+    spans.push(Span { start: start, end: end, rule: rule });
+}
+
+/// The index of the region rule left open at the end of a line, if any.
+pub type LineState = Option<usize>;
+
+/// A byte range of a line matched by a grammar rule (or no rule).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub rule: Option<usize>,
+}
+
+/// The grammars known to the editor.
+#[derive(Debug, Clone)]
+pub struct GrammarSet {
+    pub grammars: Vec<Grammar>,
+}
+
+impl GrammarSet {
+    /// Returns the grammars that are built into the editor.
+    pub fn builtin() -> GrammarSet {
+        let grammars = BUILTIN_GRAMMARS.iter()
+            .map(|source| Grammar::parse(source)
+                .expect("Invalid builtin grammar"))
+            .collect();
+        GrammarSet { grammars: grammars }
+    }
+
+    /// Loads every '.grammar' file in the given directory, replacing known
+    /// grammars of the same name.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let entries = fs::read_dir(dir)
+            .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().map_or(true, |ext| ext != "grammar") {
+                continue;
+            }
+            let grammar = Grammar::load(&path)?;
+            self.grammars.retain(|g| g.name != grammar.name);
+            self.grammars.push(grammar);
+        }
+        Ok(())
+    }
+
+    /// Finds a grammar by name.
+    pub fn by_name(&self, name: &str) -> Option<&Grammar> {
+        self.grammars.iter().find(|g| g.name == name)
+    }
+
+    /// Finds the grammar to use for the file at the given path.
+    pub fn for_path(&self, path: &Path) -> Option<&Grammar> {
+        let ext = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) => ext,
+            None => return None,
+        };
+        self.grammars.iter().find(|g| g.extensions.iter().any(|e| e == ext))
+    }
+}
+
+/// The spans of a tokenized line.
+#[derive(Debug, Clone)]
+struct HighlightedLine {
+    spans: Vec<Span>,
+    end_state: LineState,
+    /// Whether the line changed since it was tokenized.
+    stale: bool,
+}
+
+impl HighlightedLine {
+    /// Returns a line that has to be tokenized, which ended in the given
+    /// state before it changed.
+    fn stale(end_state: LineState) -> HighlightedLine {
+        HighlightedLine { spans: Vec::new(), end_state: end_state, stale: true }
+    }
+}
+
+/// Keeps the highlighting of a text up to date as it is edited.
+#[derive(Debug, Clone)]
+pub struct Highlighter {
+    pub grammar: Grammar,
+    lines: Vec<HighlightedLine>,
+}
+
+impl Highlighter {
+    /// Creates a new highlighter using the given grammar.
+    pub fn new(grammar: Grammar) -> Highlighter {
+        Highlighter { grammar: grammar, lines: Vec::new() }
+    }
+
+    /// Marks the given line and every line after it as needing to be
+    /// tokenized again.
+    pub fn invalidate_from(&mut self, line: usize) {
+        self.lines.truncate(line);
+    }
+
+    /// Replaces the lines an edit replaced with lines that have to be
+    /// tokenized. The lines after the edit are kept, and only tokenized
+    /// again if the edit changes the state they start in.
+    pub fn apply_edit(&mut self, edit: &LineEdit) {
+        let end = cmp::min(edit.line + edit.removed, self.lines.len());
+        let start = cmp::min(edit.line, end);
+        // The line after the edit started in the state the last one ended in
+        let end_state = if end > start { self.lines[end - 1].end_state } 
+            else { None };
+        let mut inserted: Vec<_> = (0 .. edit.inserted)
+            .map(|_| HighlightedLine::stale(None)).collect();
+        if let Some(last) = inserted.last_mut() {
+            last.end_state = end_state;
+        }
+        self.lines.splice(start .. end, inserted);
+    }
+
+    /// Tokenizes the lines that changed since the last update, and the lines
+    /// after them until one starts in the same state as before.
+    pub fn update(&mut self, lines: &[String]) {
+        self.lines.truncate(lines.len());
+        while self.lines.len() < lines.len() {
+            self.lines.push(HighlightedLine::stale(None));
+        }
+        let mut state = None;
+        let mut state_changed = false;
+        for (line, cached) in lines.iter().zip(self.lines.iter_mut()) {
+            if ! cached.stale && ! state_changed {
+                state = cached.end_state;
+                continue;
+            }
+            let (spans, end_state) = self.grammar.tokenize_line(line, state);
+            state_changed = end_state != cached.end_state;
+            *cached = HighlightedLine {
+                spans: spans, end_state: end_state, stale: false
+            };
+            state = end_state;
+        }
+    }
+
+    /// Returns the spans of the given line, or an empty list if it has not
+    /// been tokenized.
+    pub fn spans(&self, line: usize) -> &[Span] {
+        self.lines.get(line).map_or(&[], |l| &l.spans)
+    }
+
+    /// Returns the scope of a span.
+    pub fn scope(&self, span: &Span) -> Option<&str> {
+        span.rule.map(|index| self.grammar.rules[index].scope())
+    }
+}
+
+/// How a span of text is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpanStyle {
+    pub color: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub background: Option<Color>,
+}
+
+impl SpanStyle {
+    /// Creates a plain style with the given color.
+    pub fn new(color: Color) -> SpanStyle {
+        SpanStyle { 
+            color: color, bold: false, italic: false, underline: false,
+            strikethrough: false, background: None,
+        }
+    }
+}
+
+/// Styles for the scopes of a grammar.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub styles: HashMap<String, SpanStyle>,
+}
+
+impl Theme {
+    /// Returns the style for the given scope, if the theme has one.
+    pub fn style(&self, scope: &str) -> Option<SpanStyle> {
+        self.styles.get(scope).cloned()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        let mut styles = HashMap::new();
+        {
+            let mut add = |scope: &str, color: Color, bold: bool, italic: bool| {
+                let mut style = SpanStyle::new(color);
+                style.bold = bold;
+                style.italic = italic;
+                styles.insert(scope.to_string(), style);
+            };
+            add("comment", Color::RGBA(120, 120, 120, 255), false, true);
+            add("string", Color::RGBA(0, 130, 0, 255), false, false);
+            add("char", Color::RGBA(0, 130, 0, 255), false, false);
+            add("keyword", Color::RGBA(150, 0, 150, 255), true, false);
+            add("type", Color::RGBA(0, 90, 160, 255), false, false);
+            add("number", Color::RGBA(200, 90, 0, 255), false, false);
+            add("macro", Color::RGBA(0, 120, 140, 255), false, false);
+            add("lifetime", Color::RGBA(160, 60, 0, 255), false, true);
+            add("attribute", Color::RGBA(110, 110, 0, 255), false, false);
+            add("section", Color::RGBA(0, 90, 160, 255), true, false);
+            add("key", Color::RGBA(150, 0, 150, 255), false, false);
+            add("heading", Color::RGBA(0, 90, 160, 255), true, false);
+            add("quote", Color::RGBA(120, 120, 120, 255), false, true);
+            add("list", Color::RGBA(200, 90, 0, 255), true, false);
+            add("code", Color::RGBA(0, 130, 0, 255), false, false);
+            add("strong", Color::RGBA(0, 0, 0, 255), true, false);
+            add("emphasis", Color::RGBA(0, 0, 0, 255), false, true);
+            add("link", Color::RGBA(0, 0, 200, 255), false, false);
+        }
+        if let Some(link) = styles.get_mut("link") {
+            link.underline = true;
+        }
+        Theme { styles: styles }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use textfield::LineEdit;
+
+    fn scopes<'a>(grammar: &'a Grammar, line: &'a str, state: LineState)
+            -> (Vec<(&'a str, Option<&'a str>)>, LineState) {
+        let (spans, state) = grammar.tokenize_line(line, state);
+        let res = spans.iter().map(|s| {
+            (&line[s.start..s.end], s.rule.map(|i| grammar.rules[i].scope()))
+        }).collect();
+        (res, state)
+    }
+
+    #[test]
+    fn test_builtin_grammars_parse() {
+        let set = GrammarSet::builtin();
+        assert!(set.by_name("rust").is_some());
+        assert!(set.by_name("toml").is_some());
+        assert!(set.by_name("markdown").is_some());
+        assert_eq!(set.for_path(Path::new("a/b.rs")).unwrap().name, "rust");
+    }
+
+    #[test]
+    fn test_parse_error_has_line() {
+        let res = Grammar::parse("name x\nmatch keyword");
+        assert_eq!(res.unwrap_err(), "line 2: malformed 'match' rule");
+    }
+
+    #[test]
+    fn test_tokenize_keywords() {
+        let set = GrammarSet::builtin();
+        let rust = set.by_name("rust").unwrap();
+        let (res, state) = scopes(rust, "let x = 5; // five", None);
+        assert_eq!(res, vec![
+            ("let", Some("keyword")), (" x = ", None), ("5", Some("number")),
+            ("; ", None), ("// five", Some("comment")),
+        ]);
+        assert_eq!(state, None);
+    }
+
+    #[test]
+    fn test_tokenize_region_across_lines() {
+        let set = GrammarSet::builtin();
+        let rust = set.by_name("rust").unwrap();
+        let (res, state) = scopes(rust, "a /* b", None);
+        assert_eq!(res, vec![("a ", None), ("/* b", Some("comment"))]);
+        assert!(state.is_some());
+        let (res, state) = scopes(rust, "c */ fn", state);
+        assert_eq!(res, vec![
+            ("c */", Some("comment")), (" ", None), ("fn", Some("keyword")),
+        ]);
+        assert_eq!(state, None);
+    }
+
+    #[test]
+    fn test_highlighter_stops_when_state_matches() {
+        let set = GrammarSet::builtin();
+        let mut hl = Highlighter::new(set.by_name("rust").unwrap().clone());
+        let mut lines = vec![String::from("fn"), String::from("x */ y"),
+            String::from("fn")];
+        hl.update(&lines);
+        // Lines after an edit that keeps the state aren't tokenized again
+        hl.lines[2].spans.clear();
+        lines[0] = String::from("fn f");
+        hl.apply_edit(&LineEdit { line: 0, removed: 1, inserted: 1 });
+        hl.update(&lines);
+        assert!(hl.spans(2).is_empty());
+
+        // Opening a comment carries on into the lines after it
+        lines.insert(0, String::from("/*"));
+        hl.apply_edit(&LineEdit { line: 0, removed: 0, inserted: 1 });
+        hl.update(&lines);
+        assert_eq!(hl.scope(&hl.spans(1)[0]), Some("comment"));
+        assert_eq!(hl.spans(2)[0].end, 4);
+        assert!(hl.spans(3).is_empty());
+    }
+
+    #[test]
+    fn test_highlighter_update_from_line() {
+        let set = GrammarSet::builtin();
+        let mut hl = Highlighter::new(set.by_name("rust").unwrap().clone());
+        let mut lines = vec![String::from("fn"), String::from("x */ y")];
+        hl.update(&lines);
+        assert_eq!(hl.spans(1).len(), 1);
+
+        lines[0] = String::from("/* fn");
+        hl.invalidate_from(0);
+        hl.update(&lines);
+        assert_eq!(hl.scope(&hl.spans(1)[0]), Some("comment"));
+        assert_eq!(hl.spans(1)[0].end, 4);
+    }
+}
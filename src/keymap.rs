@@ -0,0 +1,254 @@
+//! Mapping key chords and sequences to commands.
+extern crate sdl2;
+
+use std::collections::HashMap;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::keyboard::{LCTRLMOD, RCTRLMOD, LSHIFTMOD, RSHIFTMOD};
+use sdl2::keyboard::{LALTMOD, RALTMOD, LGUIMOD, RGUIMOD};
+
+/// A key pressed together with a set of modifiers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    /// The lowercase SDL name of the key, like "left" or "c".
+    pub key: String,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// The Command key on macOS and the Windows key elsewhere.
+    pub gui: bool,
+}
+
+impl KeyChord {
+    /// Creates a chord for a key without modifiers.
+    pub fn new(key: &str) -> KeyChord {
+        KeyChord {
+            key: key.to_lowercase(), ctrl: false, shift: false, alt: false,
+            gui: false
+        }
+    }
+
+    /// Creates the chord of an SDL key event.
+    pub fn from_sdl(keycode: Keycode, keymod: Mod) -> KeyChord {
+        let mut chord = KeyChord::new(&keycode.name());
+        chord.ctrl = keymod.intersects(LCTRLMOD | RCTRLMOD);
+        chord.shift = keymod.intersects(LSHIFTMOD | RSHIFTMOD);
+        chord.alt = keymod.intersects(LALTMOD | RALTMOD);
+        chord.gui = keymod.intersects(LGUIMOD | RGUIMOD);
+        chord
+    }
+
+    /// Parses a chord like "Ctrl+Shift+Left". "Mod" is the platform's
+    /// command modifier: Cmd on macOS and Ctrl elsewhere.
+    pub fn parse(text: &str) -> Result<KeyChord, String> {
+        let parts: Vec<_> = text.split('+').collect();
+        let (key, mods) = parts.split_last().unwrap();
+        if key.is_empty() {
+            return Err(format!("'{}' has no key", text));
+        }
+        let mut chord = KeyChord::new(key);
+        for modifier in mods {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" | "option" => chord.alt = true,
+                "cmd" | "gui" | "super" => chord.gui = true,
+                "mod" => if cfg!(target_os = "macos") {
+                    chord.gui = true;
+                } else {
+                    chord.ctrl = true;
+                },
+                other => {
+                    return Err(format!("unknown modifier '{}' in '{}'",
+                        other, text));
+                },
+            }
+        }
+        Ok(chord)
+    }
+}
+
+/// Parses a sequence of chords separated by spaces, like "Ctrl+K Ctrl+C".
+pub fn parse_sequence(text: &str) -> Result<Vec<KeyChord>, String> {
+    let sequence = text.split_whitespace()
+        .map(KeyChord::parse).collect::<Result<Vec<_>, _>>()?;
+    if sequence.is_empty() {
+        Err(String::from("empty key sequence"))
+    } else {
+        Ok(sequence)
+    }
+}
+
+/// The result of pressing a key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyResult {
+    /// The key completed the binding of a command.
+    Command(String),
+    /// The key started or continued a sequence.
+    Pending,
+    /// No command is bound to the key.
+    Unbound,
+}
+
+/// The default bindings as (sequence, command) pairs.
+pub const DEFAULT_BINDINGS: [(&'static str, &'static str); 36] = [
+    ("Escape", "quit"),
+    ("Left", "move_left"),
+    ("Right", "move_right"),
+    ("Up", "move_up"),
+    ("Down", "move_down"),
+    ("Shift+Left", "select_left"),
+    ("Shift+Right", "select_right"),
+    ("Shift+Up", "select_up"),
+    ("Shift+Down", "select_down"),
+    ("Home", "move_home"),
+    ("End", "move_end"),
+    ("Shift+Home", "select_home"),
+    ("Shift+End", "select_end"),
+    ("Backspace", "delete_previous"),
+    ("Delete", "delete_next"),
+    ("Insert", "toggle_overwrite"),
+    ("Mod+Z", "undo"),
+    ("Mod+Shift+Z", "redo"),
+    ("Return", "newline"),
+    ("Shift+Return", "newline"),
+    ("Tab", "indent"),
+    ("Mod+C", "copy"),
+    ("Mod+X", "cut"),
+    ("Mod+V", "paste"),
+    ("Mod+K Mod+K", "kill_line"),
+    ("Mod+K Backspace", "kill_line_backward"),
+    ("Mod+Y", "yank"),
+    ("Alt+Y", "yank_pop"),
+    ("Mod+R", "choose_register"),
+    ("Mod+B", "toggle_bold"),
+    ("Mod+I", "toggle_italic"),
+    ("Mod+U", "toggle_underline"),
+    ("Mod+K Mod+S", "toggle_strikethrough"),
+    ("Alt+Z", "cycle_wrap"),
+    ("Mod+Return", "print_clipboard"),
+    ("Mod+Q", "quit"),
+];
+
+/// Maps key sequences to command names.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Vec<KeyChord>, String>,
+    pending: Vec<KeyChord>,
+}
+
+impl Keymap {
+    /// Creates a keymap without bindings.
+    pub fn new() -> Keymap {
+        Keymap { bindings: HashMap::new(), pending: Vec::new() }
+    }
+
+    /// Creates a keymap with the default bindings.
+    pub fn default_bindings() -> Keymap {
+        let mut keymap = Keymap::new();
+        for &(sequence, command) in DEFAULT_BINDINGS.iter() {
+            keymap.bind(parse_sequence(sequence).unwrap(), command);
+        }
+        keymap
+    }
+
+    /// Creates a keymap with the default bindings changed by the given
+    /// (sequence, command) pairs. The command "none" removes a binding.
+    pub fn with_overrides(overrides: &[(String, String)]) 
+            -> Result<Keymap, String> {
+        let mut keymap = Keymap::default_bindings();
+        for &(ref text, ref command) in overrides {
+            let sequence = parse_sequence(text)?;
+            if command == "none" {
+                keymap.unbind(&sequence);
+            } else {
+                keymap.bind(sequence, command);
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// Binds a key sequence to a command, replacing any previous binding.
+    pub fn bind(&mut self, sequence: Vec<KeyChord>, command: &str) {
+        self.bindings.insert(sequence, String::from(command));
+    }
+
+    /// Removes the binding of a key sequence.
+    pub fn unbind(&mut self, sequence: &[KeyChord]) {
+        self.bindings.remove(sequence);
+    }
+
+    /// Returns whether a sequence is pressed partially.
+    pub fn is_pending(&self) -> bool {
+        ! self.pending.is_empty()
+    }
+
+    /// Handles a key press, returning the command it completes, if any.
+    pub fn press(&mut self, chord: KeyChord) -> KeyResult {
+        self.pending.push(chord);
+        if let Some(command) = self.bindings.get(&self.pending) {
+            self.pending.clear();
+            return KeyResult::Command(command.clone());
+        }
+        let pending = &self.pending;
+        let is_prefix = self.bindings.keys().any(|sequence| {
+            sequence.len() > pending.len() && sequence.starts_with(pending)
+        });
+        if is_prefix {
+            KeyResult::Pending
+        } else {
+            // An unbound key cancels a sequence without doing anything
+            self.pending.clear();
+            KeyResult::Unbound
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> KeyChord {
+        KeyChord::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        let c = chord("Ctrl+Shift+Left");
+        assert_eq!(c.key, "left");
+        assert!(c.ctrl && c.shift && ! c.alt && ! c.gui);
+    }
+
+    #[test]
+    fn test_parse_platform_modifier() {
+        let c = chord("Mod+C");
+        assert_eq!(c.gui, cfg!(target_os = "macos"));
+        assert_eq!(c.ctrl, ! cfg!(target_os = "macos"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(KeyChord::parse("Ctrl+").is_err());
+        assert!(KeyChord::parse("Hyper+A").is_err());
+        assert!(parse_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn test_press_single() {
+        let mut keymap = Keymap::default_bindings();
+        assert_eq!(keymap.press(chord("Shift+Left")),
+            KeyResult::Command(String::from("select_left")));
+        assert_eq!(keymap.press(chord("Ctrl+Shift+Left")), KeyResult::Unbound);
+    }
+
+    #[test]
+    fn test_press_sequence() {
+        let mut keymap = Keymap::default_bindings();
+        assert_eq!(keymap.press(chord("Mod+K")), KeyResult::Pending);
+        assert!(keymap.is_pending());
+        assert_eq!(keymap.press(chord("Mod+S")),
+            KeyResult::Command(String::from("toggle_strikethrough")));
+        assert_eq!(keymap.press(chord("Mod+K")), KeyResult::Pending);
+        assert_eq!(keymap.press(chord("Left")), KeyResult::Unbound);
+        assert!(! keymap.is_pending());
+    }
+}
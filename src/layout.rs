@@ -2,203 +2,499 @@
 extern crate sdl2;
 extern crate sdl2_ttf;
 
+use std::cmp;
 use sdl2::rect::Rect;
+use measure::TextMeasurer;
+use linebreak;
+
+/// The number of columns lines wrap at when switching to column wrapping.
+pub const DEFAULT_WRAP_COLUMNS: u32 = 80;
+
+/// How lines that are too long for the view are wrapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrapMode {
+    /// Lines are never wrapped.
+    None,
+    /// Lines are wrapped between words at a width in pixels.
+    Width(u32),
+    /// Lines are wrapped between words at the width of the view, so they
+    /// are wrapped again when it is resized.
+    Viewport,
+    /// Lines are wrapped between words after a number of columns of the
+    /// average character width.
+    Columns(u32),
+    /// Lines are wrapped between any characters at the width of the view,
+    /// for languages that don't put spaces between words.
+    Character,
+}
+
+impl WrapMode {
+    /// Returns the width in pixels to wrap at in a view of the given width,
+    /// with characters of the given average width, if any.
+    pub fn width(&self, view_width: u32, char_width: u32) -> Option<u32> {
+        match *self {
+            WrapMode::None => None,
+            WrapMode::Width(width) => Some(width),
+            WrapMode::Viewport | WrapMode::Character => Some(view_width),
+            WrapMode::Columns(columns) => Some(columns * char_width),
+        }
+    }
+    
+    /// Returns the mode to switch to after this one.
+    pub fn next(&self) -> WrapMode {
+        match *self {
+            WrapMode::None => WrapMode::Viewport,
+            WrapMode::Viewport => WrapMode::Columns(DEFAULT_WRAP_COLUMNS),
+            WrapMode::Columns(_) => WrapMode::Character,
+            WrapMode::Width(_) | WrapMode::Character => WrapMode::None,
+        }
+    }
+    
+    /// Find out where to wrap a line in this mode to make it fit within the
+    /// given width. The result is a list of character boundaries to split at.
+    pub fn wrap<M: TextMeasurer>(&self, line: &str, measurer: &M, 
+            max_width: u32) -> Vec<usize> {
+        self.wrap_indented(line, measurer, max_width, 0)
+    }
+    
+    /// Like `wrap`, but with the continuation lines indented so that they
+    /// have less room.
+    pub fn wrap_indented<M: TextMeasurer>(&self, line: &str, measurer: &M, 
+            max_width: u32, indent: u32) -> Vec<usize> {
+        match *self {
+            WrapMode::None => Vec::new(),
+            WrapMode::Character => {
+                wrap_chars_indented(line, measurer, max_width, indent)
+            },
+            _ => wrap_line_indented(line, measurer, max_width, indent),
+        }
+    }
+}
+
+/// How the continuation lines of wrapped lines are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WrapStyle {
+    /// Whether continuation lines are indented as far as the leading
+    /// whitespace of their line.
+    pub hanging_indent: bool,
+    /// How many columns further continuation lines are indented when they
+    /// are indented.
+    pub extra_indent: u32,
+    /// Whether to draw a marker in the margin in front of continuation lines.
+    pub markers: bool,
+}
+
+impl WrapStyle {
+    /// Returns how far the continuation lines of a line are indented, which
+    /// is at most half the width the line wraps at. Leading tabs reach to
+    /// the tab stops of the measurer.
+    pub fn continuation_indent<M: TextMeasurer>(&self, line: &str, 
+            measurer: &M, max_width: u32, char_width: u32) -> u32 {
+        if ! self.hanging_indent {
+            return 0;
+        }
+        let leading = &line[..line.len() - line.trim_start().len()];
+        let leading_width = *glyph_positions(leading, measurer).last().unwrap();
+        let indent = leading_width as u32 + self.extra_indent * char_width;
+        cmp::min(indent, max_width / 2)
+    }
+}
+
+/// Which whitespace is drawn as faint marks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShowWhitespace {
+    None,
+    /// Every space, tab and line end.
+    Always,
+    /// The whitespace and line ends in the selection.
+    Selection,
+    /// The whitespace at the ends of lines.
+    Trailing,
+}
+
+/// Returns the columns of the whitespace characters of a line that are
+/// shown, and whether its line end is. `selected` is the range of selected
+/// columns of the line, and `end_selected` whether its line end is selected.
+pub fn shown_whitespace(line: &str, show: ShowWhitespace, 
+        selected: Option<(usize, usize)>, end_selected: bool) 
+        -> (Vec<usize>, bool) {
+    let trailing_from = line.trim_end().chars().count();
+    let whitespace = line.chars().enumerate()
+        .filter(|&(_, ch)| ch.is_whitespace())
+        .map(|(col, _)| col);
+    match show {
+        ShowWhitespace::None => (Vec::new(), false),
+        ShowWhitespace::Always => (whitespace.collect(), true),
+        ShowWhitespace::Selection => match selected {
+            Some((start, end)) => {
+                let cols = whitespace.filter(|&col| start <= col && col < end);
+                (cols.collect(), end_selected)
+            },
+            None => (Vec::new(), false),
+        },
+        ShowWhitespace::Trailing => {
+            (whitespace.filter(|&col| col >= trailing_from).collect(), false)
+        },
+    }
+}
+
+/// Returns the x offset of a visual line of a wrapped line.
+pub fn row_indent(row: usize, indent: u32) -> i32 {
+    if row > 0 { indent as i32 } else { 0 }
+}
+
+/// Returns the average width of the lowercase letters, which columns are
+/// measured in.
+pub fn average_char_width<M: TextMeasurer>(measurer: &M) -> u32 {
+    let letters = "abcdefghijklmnopqrstuvwxyz";
+    let count = letters.len() as u32;
+    cmp::max((measurer.width(letters) + count / 2) / count, 1)
+}
+
+/// Returns whether text is wider than the given width. The text starts
+/// `offset` bytes into the line it is part of, and at the start of a visual
+/// line, which its tabs are measured from.
+pub fn exceeds<M: TextMeasurer>(text: &str, offset: usize, measurer: &M, 
+        max_width: u32) -> bool {
+    let tabbed = measurer.tab_stop().is_some() && text.contains('\t');
+    // Skip measuring text that can't be too wide. A tab can advance as far
+    // as a whole tab stop.
+    if let Some(advance) = measurer.max_advance() {
+        let advance = match measurer.tab_stop() {
+            Some(tab_stop) if tabbed => cmp::max(advance, tab_stop),
+            _ => advance,
+        };
+        if text.len() as u32 * advance <= max_width {
+            return false;
+        }
+    }
+    if tabbed {
+        let width = *glyph_positions_at(text, offset, measurer).last().unwrap();
+        width as u32 > max_width
+    } else {
+        measurer.width_at(text, offset) > max_width
+    }
+}
+
+/// Returns the width of the bytes `start..end` of a line that starts
+/// `offset` bytes into the line being laid out.
+fn slice_width<M: TextMeasurer>(line: &str, offset: usize, start: usize, 
+        end: usize, measurer: &M) -> i32 {
+    measurer.width_at(&line[start..end], offset + start) as i32
+}
+
+/// Returns how much closer (when negative) or further apart two adjacent
+/// slices of a line are drawn than when they are measured apart.
+fn pair_kerning<M: TextMeasurer>(line: &str, offset: usize, start: usize, 
+        middle: usize, end: usize, measurer: &M) -> i32 {
+    slice_width(line, offset, start, end, measurer)
+        - slice_width(line, offset, start, middle, measurer)
+        - slice_width(line, offset, middle, end, measurer)
+}
+
+/// Returns the x coordinate of every character of a line, followed by the
+/// x coordinate of its end. Characters are measured as slices of the line so 
+/// that styled text can be measured, and kerned against the next character.
+/// Tabs reach to the next tab stop when the measurer has them.
+pub fn glyph_positions<M: TextMeasurer>(line: &str, measurer: &M) -> Vec<i32> {
+    glyph_positions_at(line, 0, measurer)
+}
+
+/// Like `glyph_positions`, for a visual line that starts `offset` bytes into
+/// the line being laid out.
+fn glyph_positions_at<M: TextMeasurer>(line: &str, offset: usize, 
+        measurer: &M) -> Vec<i32> {
+    let bounds: Vec<usize> = line.char_indices().map(|(i, _)| i)
+        .chain(Some(line.len())).collect();
+    let mut positions = Vec::with_capacity(bounds.len());
+    let mut x = 0;
+    let tab_stop = measurer.tab_stop().map_or(0, |stop| stop as i32);
+    let is_tab = |i: usize| {
+        tab_stop > 0 && &line[bounds[i]..bounds[i + 1]] == "\t"
+    };
+    for i in 0 .. bounds.len() - 1 {
+        positions.push(x);
+        // Tabs advance to the next tab stop and aren't kerned
+        if is_tab(i) {
+            x = (x / tab_stop + 1) * tab_stop;
+            continue;
+        }
+        x += slice_width(line, offset, bounds[i], bounds[i + 1], measurer);
+        if i + 2 < bounds.len() && ! is_tab(i + 1) {
+            x += pair_kerning(line, offset, bounds[i], bounds[i + 1], 
+                bounds[i + 2], measurer);
+        }
+    }
+    positions.push(x);
+    positions
+}
+
+/// Like `glyph_positions`, for a line wrapped into the given visual lines.
+/// Each visual line is measured from its start, like it is wrapped, and 
+/// starts where the one before it ends.
+pub fn wrapped_glyph_positions<M: TextMeasurer>(rows: &[&str], measurer: &M)
+        -> Vec<i32> {
+    let mut positions = vec![0];
+    let mut offset = 0;
+    for row in rows {
+        let start = positions.pop().unwrap();
+        let row_positions = glyph_positions_at(row, offset, measurer);
+        positions.extend(row_positions.into_iter().map(|x| start + x));
+        offset += row.len();
+    }
+    positions
+}
 
 /// Find out at which x coordinate to render a cursor in the given line of text.
-pub fn cursor_x_pos<F>(col: usize, line: &str, width_check: &F)
-        -> i32 
-        where F: Fn(&str) -> u32 {
+/// This is where the glyph at the column is drawn.
+pub fn cursor_x_pos<M: TextMeasurer>(col: usize, line: &str, measurer: &M)
+        -> i32 {
+    cursor_x_pos_at(col, line, 0, measurer)
+}
+
+/// Like `cursor_x_pos`, for a visual line that starts `offset` bytes into the
+/// line being laid out.
+fn cursor_x_pos_at<M: TextMeasurer>(col: usize, line: &str, offset: usize,
+        measurer: &M) -> i32 {
     if col == 0 {
-        0
-    } else if col >= line.len() {
-        width_check(line) as i32
-    } else {
-        let boundary: Vec<_> = line.char_indices().skip(col-1).take(2).collect();
-        let (_, left_char) = boundary[0];
-        let (right_index, right_char) = boundary[1];
-        let mut left_char_string = String::new();
-        left_char_string.push(left_char);
-        let left_char_width = width_check(&left_char_string);
-        let mut right_char_string = String::new();
-        right_char_string.push(right_char);
-        let right_char_width = width_check(&right_char_string);
-
-        let mut s = String::new();
-        s.push(left_char);
-        s.push(right_char);
-        let combined_width = width_check(&s);
-
-        let single_width = left_char_width + right_char_width;
-        let char_offset = if combined_width < single_width {
-            0
-        } else {
-            (combined_width - single_width) / 2
-        };
-        let line_width = width_check(&line[..right_index]);
-        (line_width + char_offset) as i32
+        return 0;
+    }
+    // Only the characters up to the one after the cursor matter
+    let end = line.char_indices().nth(col + 1).map_or(line.len(), |(i, _)| i);
+    let prefix = &line[..end];
+    let positions = glyph_positions_at(prefix, offset, measurer);
+    if col + 1 >= positions.len() {
+        return *positions.last().unwrap();
+    }
+    // Place the cursor in the middle of the space kerning adds
+    let left = prefix.char_indices().nth(col - 1).unwrap().0;
+    let right = prefix.char_indices().nth(col).unwrap().0;
+    if &prefix[left..right] == "\t" {
+        return positions[col];
     }
+    let kerning = positions[col] - positions[col - 1] 
+        - slice_width(prefix, offset, left, right, measurer);
+    if kerning > 0 { positions[col] - kerning / 2 } else { positions[col] }
 }
 
 /// Find out where to render a cursor in the given line of text.
 /// Returns a line number and the x position of the cursor in it.
 /// The line number is only relevant when the text is being wrapped.
-pub fn cursor_pos<F>(col: usize, lines: &Vec<&str>, width_check: &F)
-        -> (usize, i32) 
-        where F: Fn(&str) -> u32 {
+pub fn cursor_pos<M: TextMeasurer>(col: usize, lines: &Vec<&str>, 
+        measurer: &M) -> (usize, i32) {
+    wrapped_cursor_pos(col, lines, measurer, false)
+}
+
+/// Like `cursor_pos`, but a cursor where a line wraps is at the end of the
+/// visual line before the wrap instead of at the start of the one after.
+pub fn cursor_pos_before_wrap<M: TextMeasurer>(col: usize, lines: &Vec<&str>,
+        measurer: &M) -> (usize, i32) {
+    wrapped_cursor_pos(col, lines, measurer, true)
+}
+
+fn wrapped_cursor_pos<M: TextMeasurer>(col: usize, lines: &Vec<&str>, 
+        measurer: &M, before_wrap: bool) -> (usize, i32) {
     if col == 0 {
         return (0, 0);
     } else if lines.len() > 1 {
         let mut col_remainder = col;
+        // The visual lines are measured at their place in the line
+        let mut offset = 0;
         let last = lines.len() - 1;
         for (i, line) in lines.iter().enumerate() {
-            if col_remainder < line.chars().count() || i == last {
-                let x = cursor_x_pos(col_remainder, line, width_check);
+            let len = line.chars().count();
+            let on_line = if before_wrap { 
+                col_remainder <= len 
+            } else { 
+                col_remainder < len 
+            };
+            if on_line || i == last {
+                let x = cursor_x_pos_at(col_remainder, line, offset, measurer);
                 return (i, x);
             } else {
-                col_remainder -= line.chars().count();
+                col_remainder -= len;
+                offset += line.len();
             }
         }
         unreachable!();
     } else {
-        let x = cursor_x_pos(col, lines[0], width_check);
+        let x = cursor_x_pos(col, lines[0], measurer);
         return (0, x);
     }
 }
 
-/// Find out where to wrap a word to make it fit based on the given function.
-/// The result is a list of character boundaries to split at.
-pub fn wrap_word<'a, F>(line: &'a str, should_wrap: &F)
-        -> Vec<usize> 
-        where F: Fn(&str) -> bool {
+/// Find out which column of a line of text is closest to an x coordinate.
+pub fn col_at_x<M: TextMeasurer>(line: &str, x: i32, measurer: &M) -> usize {
+    col_at_x_at(line, 0, x, measurer)
+}
+
+/// Like `col_at_x`, for the visual line `row` of a wrapped line.
+pub fn row_col_at_x<M: TextMeasurer>(rows: &[&str], row: usize, x: i32, 
+        measurer: &M) -> usize {
+    let offset = rows[..row].iter().map(|text| text.len()).sum();
+    col_at_x_at(rows[row], offset, x, measurer)
+}
+
+/// Like `col_at_x`, for a visual line that starts `offset` bytes into the
+/// line being laid out.
+fn col_at_x_at<M: TextMeasurer>(line: &str, offset: usize, x: i32, 
+        measurer: &M) -> usize {
+    let positions = glyph_positions_at(line, offset, measurer);
+    let mut best = 0;
+    for (col, &pos) in positions.iter().enumerate() {
+        if (pos - x).abs() < (positions[best] - x).abs() {
+            best = col;
+        }
+    }
+    best
+}
+
+/// Splits a line into its visual lines at the indices it wraps at.
+pub fn split_wrapped<'a>(line: &'a str, breaks: &[usize]) -> Vec<&'a str> {
+    let mut lines = Vec::with_capacity(breaks.len() + 1);
+    let mut start = 0;
+    for &index in breaks {
+        lines.push(&line[start..index]);
+        start = index;
+    }
+    lines.push(&line[start..]);
+    lines
+}
+
+/// Find out where to wrap a word to make it fit within the given width.
+/// The result is a list of character boundaries to split at, which are never
+/// inside characters that are drawn together.
+pub fn wrap_word<'a, M: TextMeasurer>(line: &'a str, measurer: &M, 
+        max_width: u32) -> Vec<usize> {
+    wrap_word_at(line, 0, measurer, max_width)
+}
+
+/// Like `wrap_word`, for a word that starts `offset` bytes into the line
+/// being laid out.
+fn wrap_word_at<M: TextMeasurer>(line: &str, offset: usize, measurer: &M, 
+        max_width: u32) -> Vec<usize> {
+    let should_wrap = |start: usize, end: usize| {
+        exceeds(&line[start..end], offset + start, measurer, max_width)
+    };
     let mut indices = Vec::new();
     let mut start = 0;
     let mut last_index = 0;
+    let mut prev = None;
     for (cur_index, ch) in line.char_indices() {
-        if ch.is_whitespace() { // Ignore trailing whitespace
+        if linebreak::hangs(ch) { // Ignore trailing whitespace
             break;
         }
         let next_index = cur_index + ch.len_utf8();
-        if should_wrap(&line[start..next_index]) {
+        let joined = prev.map_or(false, |prev| linebreak::joins(prev, ch));
+        if ! joined && should_wrap(start, next_index) {
             if start != last_index {
-                //println!("--- '{}'", &line[start..last_index]);
                 indices.push(last_index);
             }
             start = last_index;
-            
         }
         last_index = next_index;
+        prev = Some(ch);
     }
-    //println!("--- '{}'", &line[start..]);
     indices
 }
 
-/// Find out where to wrap the given line of text to make it fit based on the
-/// given function. The result is a list of character boundaries to split at.
-pub fn wrap_line<'a, F>(line: &'a str, should_wrap: &F)
-        -> Vec<usize> 
-        where F: Fn(&str) -> bool {
-    if ! should_wrap(line) {
-        vec![]
-    } else {
-        let mut indices = Vec::new();
-        
-        let mut start = 0;
-        let mut cur_word_begin = 0;
-        let mut cur_word_end = 0;
-        let mut last_word_begin = 0;
-        
-        let mut was_whitespace = false;
-        
-        for (cur_index, ch) in line.char_indices() {
-            let next_index = cur_index + ch.len_utf8();
-            if ch.is_whitespace() {
-                if ! was_whitespace { // New spacing begins
-                    last_word_begin = cur_word_begin;
-                }
-                cur_word_begin = next_index;
-                was_whitespace = true;
-            
-            } else {
-                if was_whitespace { // New word begins
-                    
-                    if should_wrap(&line[start..cur_word_end]) {
-                        if start == last_word_begin { // Single word read
-                            let part = &line[start..cur_word_begin];
-                            //println!("- '{}'", part);
-                            for index in wrap_word(part, should_wrap) {
-                                indices.push(start + index);
-                            };
-                            if cur_word_begin != line.len() {
-                                indices.push(start + part.len());
-                            }
-                            start = cur_word_begin;
-                        } else { // Two or more words read
-                            //println!("- '{}'", &line[start..last_word_begin]);
-                            indices.push(last_word_begin);
-                            start = last_word_begin;
-                        }
-                    }
-                }
-                cur_word_end = next_index;
-                was_whitespace = false;
-            }
-        }
-        
-        //println!("Remainder:");
-        // Get the remainder
-        // If the line doesn't end with a space character, act as if a new word just started
-        if cur_word_begin != line.len() {
-            last_word_begin = cur_word_begin;
-            cur_word_begin = line.len();
+/// Returns a line without the whitespace that hangs at its end.
+fn trim_hanging(text: &str) -> &str {
+    text.trim_end_matches(linebreak::hangs)
+}
+
+/// Find out where to wrap the given line of text to make it fit within the
+/// given width, breaking where the Unicode line breaking algorithm allows.
+/// Whitespace hangs past the width, and parts that can't be broken but are
+/// too wide are broken between characters. The result is a list of character
+/// boundaries to split at.
+pub fn wrap_line<'a, M: TextMeasurer>(line: &'a str, measurer: &M, 
+        max_width: u32) -> Vec<usize> {
+    wrap_line_indented(line, measurer, max_width, 0)
+}
+
+/// Like `wrap_line`, but the lines after the first are indented, which
+/// leaves them less of the width.
+pub fn wrap_line_indented<'a, M: TextMeasurer>(line: &'a str, measurer: &M, 
+        max_width: u32, indent: u32) -> Vec<usize> {
+    let rest_width = cmp::max(max_width.saturating_sub(indent), 1);
+    let width_at = |start: usize| if start == 0 { max_width } else { rest_width };
+    // Whether a visual line from `start` is too wide, without the
+    // whitespace that hangs at its end
+    let should_wrap = |start: usize, end: usize| {
+        let text = trim_hanging(&line[start..end]);
+        exceeds(text, start, measurer, width_at(start))
+    };
+    let mut indices = Vec::new();
+    if ! exceeds(line, 0, measurer, max_width) {
+        return indices;
+    }
+    // The start of the current visual line, and the end of the parts on it
+    let mut start = 0;
+    let mut end = 0;
+    let opportunities = linebreak::break_opportunities(line);
+    for next in opportunities.into_iter().chain(Some(line.len())) {
+        if end > start && should_wrap(start, next) {
+            indices.push(end);
+            start = end;
         }
-        
-        // The remainder should be wrapped
-        if should_wrap(&line[start..cur_word_end]) {
-            if start == last_word_begin { // Single word
-                let part = &line[start..];
-                //println!("- '{}'", part);
-                for index in wrap_word(part, should_wrap) {
+        // A part too wide for a visual line is broken between characters,
+        // a visual line at a time since the first one can be wider
+        while should_wrap(start, next) {
+            let part = &line[start..next];
+            match wrap_word_at(part, start, measurer, width_at(start)).first() {
+                Some(&index) => {
                     indices.push(start + index);
-                }
-            } else { // Multiple words
-                if last_word_begin != line.len() {
-                    let part = &line[start..last_word_begin];
-                    //println!("- '{}'", part);
-                    if should_wrap(part) {
-                        for index in wrap_word(part, should_wrap) {
-                            indices.push(start + index);
-                        }
-                    }
-                    indices.push(last_word_begin);
-                    
-                    start = last_word_begin;
-                    if start != line.len() {
-                        let part = &line[start..];
-                        //println!("- '{}'", part);
-                        if should_wrap(part) {
-                            for index in wrap_word(&line[start..], should_wrap) {
-                                indices.push(start + index);
-                            }
-                        }                        
-                    }
-                }
+                    start += index;
+                },
+                None => break,
             }
-        } else {
-            //println!("- '{}'", &line[start..]);
         }
-        indices
+        end = next;
     }
+    indices
+}
+
+/// Find out where to wrap a line between any characters to make it fit within
+/// the given width. Whitespace hangs past the width instead of starting a
+/// line. The result is a list of character boundaries to split at.
+pub fn wrap_chars<M: TextMeasurer>(line: &str, measurer: &M, max_width: u32)
+        -> Vec<usize> {
+    wrap_chars_indented(line, measurer, max_width, 0)
+}
+
+/// Like `wrap_chars`, but the lines after the first are indented, which
+/// leaves them less of the width.
+pub fn wrap_chars_indented<M: TextMeasurer>(line: &str, measurer: &M, 
+        max_width: u32, indent: u32) -> Vec<usize> {
+    let rest_width = cmp::max(max_width.saturating_sub(indent), 1);
+    let mut indices = Vec::new();
+    let mut start = 0;
+    let mut prev = None;
+    for (index, ch) in line.char_indices() {
+        let joined = prev.map_or(false, |prev| linebreak::joins(prev, ch));
+        prev = Some(ch);
+        if index == start || joined || linebreak::hangs(ch) {
+            continue;
+        }
+        let end = index + ch.len_utf8();
+        let width = if start == 0 { max_width } else { rest_width };
+        if exceeds(&line[start..end], start, measurer, width) {
+            indices.push(index);
+            start = index;
+        }
+    }
+    indices
 }
 
 /// Returns the rectangles of a selection which starts and ends on the same line.
-pub fn selection_single_line<F>(lines: &Vec<&str>, start: usize, end: usize, 
-        width_check: &F, line_width: u32, line_height: u32) 
-        -> Vec<Rect> 
-        where F: Fn(&str) -> u32 {
+pub fn selection_single_line<M: TextMeasurer>(lines: &Vec<&str>, start: usize,
+        end: usize, measurer: &M, line_width: u32) -> Vec<Rect> {
+    let line_height = measurer.line_height();
     if lines.len() > 1 {
         let mut selections = Vec::new();
-        let (sl, sx) = cursor_pos(start, &lines, width_check);
-        let (el, ex) = cursor_pos(end, &lines, width_check);
+        let (sl, sx) = cursor_pos(start, &lines, measurer);
+        let (el, ex) = cursor_pos(end, &lines, measurer);
         if sl == el {
             let rect = Rect::new(
                 sx, (sl as u32 * line_height) as i32, 
@@ -227,21 +523,20 @@ pub fn selection_single_line<F>(lines: &Vec<&str>, start: usize, end: usize,
         }
         selections
     } else {
-        let sx = cursor_x_pos(start, lines[0], width_check);
-        let ex = cursor_x_pos(end, lines[0], width_check);
+        let sx = cursor_x_pos(start, lines[0], measurer);
+        let ex = cursor_x_pos(end, lines[0], measurer);
         vec![Rect::new(sx, 0, (ex - sx) as u32, line_height)]
     }
 }
 
 /// Returns the rectangles of the first line of a selection that spans multiple
 /// lines.
-pub fn selection_first_line<F>(lines: &Vec<&str>, start: usize, width_check: &F, 
-        line_width: u32, line_height: u32) 
-        -> Vec<Rect> 
-        where F: Fn(&str) -> u32 {
+pub fn selection_first_line<M: TextMeasurer>(lines: &Vec<&str>, start: usize, 
+        measurer: &M, line_width: u32) -> Vec<Rect> {
+    let line_height = measurer.line_height();
     if lines.len() > 1 {
         let mut selections = Vec::new();
-        let (lineno, cx) = cursor_pos(start, &lines, width_check);
+        let (lineno, cx) = cursor_pos(start, &lines, measurer);
         let first = Rect::new(
             cx, (lineno as u32 * line_height) as i32,
             line_width - cx as u32, line_height
@@ -258,7 +553,7 @@ pub fn selection_first_line<F>(lines: &Vec<&str>, start: usize, width_check: &F,
         }
         selections
     } else {
-        let cx = cursor_x_pos(start, lines[0], width_check);
+        let cx = cursor_x_pos(start, lines[0], measurer);
         if cx as u32 >= line_width {
             Vec::new()
         } else {
@@ -275,13 +570,12 @@ pub fn selection_middle_line(line_count: usize, line_width: u32, line_height: u3
 
 /// Returns the rectangles of the last line of a selection that spans multiple
 /// lines.
-pub fn selection_last_line<F>(lines: &Vec<&str>, end: usize, width_check: &F,
-        line_width: u32, line_height: u32) 
-        -> Vec<Rect> 
-        where F: Fn(&str) -> u32 {
+pub fn selection_last_line<M: TextMeasurer>(lines: &Vec<&str>, end: usize, 
+        measurer: &M, line_width: u32) -> Vec<Rect> {
+    let line_height = measurer.line_height();
     if lines.len() > 1 {
         let mut selections = Vec::new();
-        let (lineno, cx) = cursor_pos(end, &lines, width_check);
+        let (lineno, cx) = cursor_pos(end, &lines, measurer);
         let last = Rect::new(
             0, (lineno as u32 * line_height) as i32,
             cx as u32, line_height
@@ -297,7 +591,7 @@ pub fn selection_last_line<F>(lines: &Vec<&str>, end: usize, width_check: &F,
         }
         selections
     } else {
-        let cx = cursor_x_pos(end, lines[0], width_check);
+        let cx = cursor_x_pos(end, lines[0], measurer);
         let rect = Rect::new(0, 0, cx as u32, line_height);
         vec![rect]
     }
@@ -306,6 +600,7 @@ pub fn selection_last_line<F>(lines: &Vec<&str>, end: usize, width_check: &F,
 #[cfg(test)]
 mod tests {
     use super::*;
+    use measure::{FixedWidthMeasurer, TabbedMeasurer};
     
     const TEXT: &'static str = "\
     123\
@@ -338,26 +633,13 @@ mod tests {
     789\
     0 ";
     
-    fn width_check(t: &str) -> u32 {
-        t.chars().count() as u32
-    }
-    
-    fn should_wrap_3(t: &str) -> bool {
-        t.chars().count() > 3
-    }
+    /// Measures every character as one pixel wide.
+    const MEASURER: FixedWidthMeasurer = FixedWidthMeasurer { 
+        advance: 1, line_height: 1 
+    };
     
-    fn should_wrap_2(t: &str) -> bool {
-        t.chars().count() > 2
-    }
-    
-    fn should_wrap_6(t: &str) -> bool {
-        t.chars().count() > 6
-    }
-    
-    fn wrap_word_text<'a, F>(line: &'a str, should_wrap: &F) 
-            -> Vec<&'a str> 
-            where F: Fn(&str) -> bool {
-        let indices = wrap_word(line, should_wrap);
+    fn wrap_word_text<'a>(line: &'a str, max_width: u32) -> Vec<&'a str> {
+        let indices = wrap_word(line, &MEASURER, max_width);
         if ! indices.is_empty() {
             let mut lines = Vec::new();
             let mut start = 0;
@@ -372,10 +654,8 @@ mod tests {
         }
     }
     
-    fn wrap_line_text<'a, F>(line: &'a str, should_wrap: &F) 
-            -> Vec<&'a str> 
-            where F: Fn(&str) -> bool {
-        let indices = wrap_line(line, should_wrap);
+    fn wrap_line_text<'a>(line: &'a str, max_width: u32) -> Vec<&'a str> {
+        let indices = wrap_line(line, &MEASURER, max_width);
         if ! indices.is_empty() {
             let mut lines = Vec::new();
             let mut start = 0;
@@ -390,98 +670,323 @@ mod tests {
         }
     }
     
+    fn wrap_mode_text<'a>(mode: WrapMode, line: &'a str, max_width: u32) 
+            -> Vec<&'a str> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for index in mode.wrap(line, &MEASURER, max_width) {
+            lines.push(&line[start..index]);
+            start = index;
+        }
+        lines.push(&line[start..]);
+        lines
+    }
+    
+    #[test]
+    fn test_wrap_modes() {
+        assert_eq!(wrap_mode_text(WrapMode::None, "ab cd", 2), vec!["ab cd"]);
+        assert_eq!(wrap_mode_text(WrapMode::Viewport, "ab cd", 3), 
+            vec!["ab ", "cd"]);
+        assert_eq!(wrap_mode_text(WrapMode::Character, "abc de", 4), 
+            vec!["abc ", "de"]);
+    }
+    
+    #[test]
+    fn test_wrap_chars_without_spaces() {
+        assert_eq!(wrap_mode_text(WrapMode::Character, "日本語のテキスト", 3),
+            vec!["日本語", "のテキ", "スト"]);
+        // Spaces hang at the end instead of starting a line
+        assert_eq!(wrap_mode_text(WrapMode::Character, "ab   cd", 2), 
+            vec!["ab   ", "cd"]);
+    }
+    
+    #[test]
+    fn test_wrap_widths() {
+        assert_eq!(WrapMode::None.width(300, 7), None);
+        assert_eq!(WrapMode::Width(200).width(300, 7), Some(200));
+        assert_eq!(WrapMode::Viewport.width(300, 7), Some(300));
+        assert_eq!(WrapMode::Columns(10).width(300, 7), Some(70));
+        assert_eq!(average_char_width(&FixedWidthMeasurer::new(7, 10)), 7);
+    }
+    
+    #[test]
+    fn test_wrap_mode_cycle() {
+        let mut mode = WrapMode::None;
+        for _ in 0..4 {
+            mode = mode.next();
+        }
+        assert_eq!(mode, WrapMode::None);
+        assert_eq!(WrapMode::Viewport.next(), 
+            WrapMode::Columns(DEFAULT_WRAP_COLUMNS));
+    }
+    
     #[test]
     fn test_wrap_word() {
-        let res = wrap_word_text(TEXT, &should_wrap_3);
+        let res = wrap_word_text(TEXT, 3);
         assert_eq!(res, vec!["123", "456", "789", "0"]);
     }
     
     #[test]
     fn test_wrap_line_long() {
-        let res = wrap_line_text(TEXT, &should_wrap_3);
+        let res = wrap_line_text(TEXT, 3);
         assert_eq!(res, vec!["123", "456", "789", "0"]);
     }
     
     #[test]
     fn test_wrap_line_long_multiple() {
-        let res = wrap_line_text(TEXT3, &should_wrap_3);
+        let res = wrap_line_text(TEXT3, 3);
         assert_eq!(res, vec!["123", "456", "789", "0 ", "333 "]);
     }
     
     #[test]
     fn test_wrap_line_long_multiple_non_first() {
-        let res = wrap_line_text(TEXT5, &should_wrap_3);
+        let res = wrap_line_text(TEXT5, 3);
         assert_eq!(res, vec!["333 ", "123", "456", "789", "0 "]);
     }
     
     #[test]
     fn test_wrap_line_long_no_remainder() {
-        let res = wrap_line_text(TEXT, &should_wrap_2);
+        let res = wrap_line_text(TEXT, 2);
         assert_eq!(res, vec!["12", "34", "56", "78", "90"]);
     }
     
     #[test]
-    fn test_wrap_line_multiple_three() {
-        let res = wrap_line_text(TEXT2, &should_wrap_3);
+    fn test_wrap_line_multiple_four() {
+        let res = wrap_line_text(TEXT2, 4);
         assert_eq!(res, vec!["333 ", "22     ", "1 ", "4444  ", "22 ", "333   "]);
     }
     
+    #[test]
+    fn test_wrap_line_breaks_word_wider_than_line() {
+        // Words that don't fit on a line of their own are broken between
+        // characters wherever they are in the line
+        let res = wrap_line_text(TEXT2, 3);
+        assert_eq!(res, vec!["333 ", "22     ", "1 ", "444", "4  ", "22 ", 
+            "333   "]);
+    }
+    
     #[test]
     fn test_wrap_line_multiple_six() {
-        let res = wrap_line_text(TEXT2, &should_wrap_6);
+        let res = wrap_line_text(TEXT2, 6);
         assert_eq!(res, vec!["333 22     ", "1 4444  ", "22 333   "]);
     }
     
     #[test]
     fn test_wrap_line_no_trailing_space() {
-        let res = wrap_line_text(TEXT4, &should_wrap_6);
+        let res = wrap_line_text(TEXT4, 6);
         assert_eq!(res, vec!["333 22 ", "1 4444 ", "22 333"]);
     }
     
+    #[test]
+    fn test_wrap_line_cjk() {
+        assert_eq!(wrap_line_text("日本語のテキスト。", 3), 
+            vec!["日本語", "のテキ", "スト。"]);
+        // Commas don't start lines, so the character before moves with it
+        assert_eq!(wrap_line_text("こんにちは、世界", 5), 
+            vec!["こんにち", "は、世界"]);
+        assert_eq!(wrap_line_text("see 日本", 5), vec!["see 日", "本"]);
+    }
+    
+    #[test]
+    fn test_wrap_line_non_breaking_space() {
+        assert_eq!(wrap_line_text("10\u{A0}kg of flour", 6), 
+            vec!["10\u{A0}kg ", "of ", "flour"]);
+        assert_eq!(wrap_line_text("a 10\u{A0}kg", 6), vec!["a ", "10\u{A0}kg"]);
+        // A non-breaking space doesn't hang
+        assert_eq!(wrap_line_text("abc\u{A0}def", 4), vec!["abc\u{A0}", "def"]);
+    }
+    
+    #[test]
+    fn test_wrap_line_hyphens() {
+        assert_eq!(wrap_line_text("a well-known fact", 8), 
+            vec!["a well-", "known ", "fact"]);
+        assert_eq!(wrap_line_text("see http://x.org/a/b", 12), 
+            vec!["see http://", "x.org/a/b"]);
+        assert_eq!(wrap_line_text("from -5 up", 4), vec!["from ", "-5 ", "up"]);
+    }
+    
+    #[test]
+    fn test_wrap_line_emoji() {
+        assert_eq!(wrap_line_text("👍🏽👍🏽", 2), vec!["👍🏽", "👍🏽"]);
+        // A sequence of joined emoji is never broken, even when too wide
+        assert_eq!(wrap_line_text("👩\u{200D}👩\u{200D}👧", 2), 
+            vec!["👩\u{200D}👩\u{200D}👧"]);
+        assert_eq!(wrap_mode_text(WrapMode::Character, "e\u{301}e\u{301}", 1), 
+            vec!["e\u{301}", "e\u{301}"]);
+    }
+    
+    fn split_text<'a>(line: &'a str, breaks: Vec<usize>) -> Vec<&'a str> {
+        split_wrapped(line, &breaks)
+    }
+    
+    #[test]
+    fn test_wrap_line_indented() {
+        let line = "  aaa bbb ccc";
+        assert_eq!(split_text(line, wrap_line_indented(line, &MEASURER, 7, 0)),
+            vec!["  aaa ", "bbb ccc"]);
+        // The continuation lines only have 5 of the 7 columns
+        assert_eq!(split_text(line, wrap_line_indented(line, &MEASURER, 7, 2)),
+            vec!["  aaa ", "bbb ", "ccc"]);
+        let line = "abcdefgh";
+        assert_eq!(split_text(line, wrap_line_indented(line, &MEASURER, 4, 2)),
+            vec!["abcd", "ef", "gh"]);
+        assert_eq!(split_text(line, wrap_chars_indented(line, &MEASURER, 4, 2)),
+            vec!["abcd", "ef", "gh"]);
+    }
+    
+    #[test]
+    fn test_continuation_indent() {
+        let mut style = WrapStyle::default();
+        assert_eq!(style.continuation_indent("  x", &MEASURER, 100, 1), 0);
+        style.hanging_indent = true;
+        style.extra_indent = 2;
+        assert_eq!(style.continuation_indent("  x", &MEASURER, 100, 1), 4);
+        assert_eq!(style.continuation_indent("\tx", &MEASURER, 100, 3), 7);
+        // Continuation lines keep at least half of the width
+        assert_eq!(style.continuation_indent("  x", &MEASURER, 6, 1), 3);
+        // Leading tabs reach to the tab stops
+        let measurer = TabbedMeasurer::new(MEASURER, 4);
+        assert_eq!(style.continuation_indent("\tx", &measurer, 100, 3), 10);
+        assert_eq!(style.continuation_indent(" \t x", &measurer, 100, 3), 11);
+    }
+    
+    #[test]
+    fn test_tab_stops() {
+        let measurer = TabbedMeasurer::new(MEASURER, 4);
+        assert_eq!(glyph_positions("a\tb", &measurer), vec![0, 1, 4, 5]);
+        assert_eq!(glyph_positions("abcd\tx", &measurer), 
+            vec![0, 1, 2, 3, 4, 8, 9]);
+        assert_eq!(cursor_x_pos(2, "a\tb", &measurer), 4);
+        assert_eq!(glyph_positions("a\tb", &MEASURER), vec![0, 1, 2, 3]);
+        // Tabs on continuation lines reach to stops from the line's start
+        assert_eq!(wrapped_glyph_positions(&["ab", "c\td"], &measurer), 
+            vec![0, 1, 2, 3, 6, 7]);
+    }
+    
+    #[test]
+    fn test_wrap_line_tabs() {
+        let measurer = TabbedMeasurer::new(MEASURER, 4);
+        // The tab reaches to 4, so "a\tbc" is 6 wide
+        let line = "a\tbc de";
+        assert_eq!(split_text(line, wrap_line(line, &measurer, 6)), 
+            vec!["a\tbc ", "de"]);
+        assert_eq!(split_text(line, wrap_line(line, &measurer, 5)), 
+            vec!["a\t", "bc de"]);
+        // Tabs on continuation lines are measured from their start
+        let line = "abcdefg\thi";
+        assert_eq!(split_text(line, wrap_chars(line, &measurer, 5)), 
+            vec!["abcde", "fg\th", "i"]);
+    }
+    
+    #[test]
+    fn test_shown_whitespace() {
+        let line = "a b\t c  ";
+        assert_eq!(shown_whitespace(line, ShowWhitespace::Always, None, false),
+            (vec![1, 3, 4, 6, 7], true));
+        assert_eq!(shown_whitespace(line, ShowWhitespace::Trailing, None, false),
+            (vec![6, 7], false));
+        assert_eq!(shown_whitespace(line, ShowWhitespace::Selection, 
+            Some((2, 5)), false), (vec![3, 4], false));
+        assert_eq!(shown_whitespace(line, ShowWhitespace::Selection, 
+            Some((7, 8)), true), (vec![7], true));
+        assert_eq!(shown_whitespace(line, ShowWhitespace::None, None, true),
+            (vec![], false));
+    }
+    
     #[test]
     fn test_cursor_x_pos_zero() {
-        let res = cursor_x_pos(0, "hello", &width_check);
+        let res = cursor_x_pos(0, "hello", &MEASURER);
         assert_eq!(res, 0);
     }
     
     #[test]
     fn test_cursor_x_pos_non_zero() {
-        let res = cursor_x_pos(2, "hello", &width_check);
+        let res = cursor_x_pos(2, "hello", &MEASURER);
         assert_eq!(res, 2);
     }
     
     #[test]
     fn test_cursor_x_pos_past_end() {
-        let res = cursor_x_pos(8, "hello", &width_check);
+        let res = cursor_x_pos(8, "hello", &MEASURER);
         assert_eq!(res, 5);
     }
     
+    #[test]
+    fn test_cursor_x_pos_multibyte_end() {
+        let res = cursor_x_pos(3, "日本語", &MEASURER);
+        assert_eq!(res, 3);
+    }
+    
+    /// Spaces "AV" two pixels further apart than its advances.
+    struct Kerned;
+    
+    impl TextMeasurer for Kerned {
+        fn width(&self, text: &str) -> u32 {
+            MEASURER.width(text) + text.matches("AV").count() as u32 * 2
+        }
+        fn advance(&self, _: char) -> u32 { 1 }
+        fn line_height(&self) -> u32 { 1 }
+        fn ascent(&self) -> i32 { 1 }
+        fn descent(&self) -> i32 { 0 }
+    }
+    
+    #[test]
+    fn test_cursor_x_pos_kerning() {
+        let res = cursor_x_pos(1, "AV", &Kerned);
+        assert_eq!(res, 2);
+    }
+    
+    #[test]
+    fn test_glyph_positions_kerning() {
+        assert_eq!(glyph_positions("", &Kerned), vec![0]);
+        assert_eq!(glyph_positions("AVA", &Kerned), vec![0, 3, 4, 5]);
+        assert_eq!(glyph_positions("日本", &MEASURER), vec![0, 1, 2]);
+    }
+    
     #[test]
     fn test_cursor_pos_wrapped_first_line() {
-        let lines = wrap_line_text(TEXT, &should_wrap_3);
-        let res = cursor_pos(2, &lines, &width_check);
+        let lines = wrap_line_text(TEXT, 3);
+        let res = cursor_pos(2, &lines, &MEASURER);
         assert_eq!(res, (0, 2))
     }
     
     #[test]
     fn test_cursor_pos_wrapped_middle_line() {
-        let lines = wrap_line_text(TEXT, &should_wrap_3);
-        let res = cursor_pos(3, &lines, &width_check);
+        let lines = wrap_line_text(TEXT, 3);
+        let res = cursor_pos(3, &lines, &MEASURER);
         assert_eq!(res, (1, 0))
     }
     
     #[test]
     fn test_cursor_pos_wrapped_last_line() {
-        let lines = wrap_line_text(TEXT, &should_wrap_3);
-        let res = cursor_pos(9, &lines, &width_check);
+        let lines = wrap_line_text(TEXT, 3);
+        let res = cursor_pos(9, &lines, &MEASURER);
         assert_eq!(res, (3, 0))
     }
     
     #[test]
     fn test_cursor_pos_wrapped_past_end() {
-        let lines = wrap_line_text(TEXT, &should_wrap_3);
-        let res = cursor_pos(11, &lines, &width_check);
+        let lines = wrap_line_text(TEXT, 3);
+        let res = cursor_pos(11, &lines, &MEASURER);
         assert_eq!(res, (3, 1))
     }
+    
+    #[test]
+    fn test_cursor_pos_before_wrap() {
+        let lines = wrap_line_text(TEXT, 3);
+        assert_eq!(cursor_pos(3, &lines, &MEASURER), (1, 0));
+        assert_eq!(cursor_pos_before_wrap(3, &lines, &MEASURER), (0, 3));
+        assert_eq!(cursor_pos_before_wrap(4, &lines, &MEASURER), (1, 1));
+    }
+    
+    #[test]
+    fn test_col_at_x() {
+        let measurer = FixedWidthMeasurer::new(10, 10);
+        assert_eq!(col_at_x("abc", -5, &measurer), 0);
+        assert_eq!(col_at_x("abc", 14, &measurer), 1);
+        assert_eq!(col_at_x("abc", 16, &measurer), 2);
+        assert_eq!(col_at_x("abc", 100, &measurer), 3);
+        assert_eq!(split_wrapped("abcd", &[1, 3]), vec!["a", "bc", "d"]);
+    }
 }
 
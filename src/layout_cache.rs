@@ -0,0 +1,335 @@
+//! Where the lines of a text field wrap, kept between frames and updated for
+//! the lines that edits touch.
+
+use std::cmp;
+use textfield::LineEdit;
+use layout::WrapMode;
+
+/// How a line was laid out.
+#[derive(Debug, Clone)]
+struct LineLayout<K> {
+    /// What the line was laid out with besides the wrapping, or nothing
+    /// when it has to be laid out again.
+    key: Option<K>,
+    /// The byte indices where the line wraps. These are kept when the line is
+    /// invalidated, as a guess of how many visual lines it has.
+    breaks: Vec<usize>,
+}
+
+impl<K> LineLayout<K> {
+    fn unknown() -> LineLayout<K> {
+        LineLayout { key: None, breaks: Vec::new() }
+    }
+
+    fn visual_lines(&self) -> usize {
+        self.breaks.len() + 1
+    }
+}
+
+/// The first visual line shown in a view, as a line and which of its visual
+/// lines it is. The view stays at the line when the text is wrapped again.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScrollAnchor {
+    pub line: usize,
+    pub row: usize,
+}
+
+/// The wrapped layout of every line of a text field, with the number of
+/// visual lines before each line kept in a Fenwick tree so that going from a
+/// line to a visual line and back is O(log n).
+///
+/// Lines that haven't been laid out count as one visual line.
+#[derive(Debug, Clone)]
+pub struct LayoutCache<K> {
+    lines: Vec<LineLayout<K>>,
+    /// Partial sums of the visual line counts, indexed from 1.
+    tree: Vec<usize>,
+    /// How and at which width the lines were wrapped.
+    wrap: (WrapMode, Option<u32>),
+    anchor: ScrollAnchor,
+}
+
+/// Returns the lowest set bit of a Fenwick tree index.
+fn low_bit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
+impl<K: PartialEq> LayoutCache<K> {
+    pub fn new() -> LayoutCache<K> {
+        LayoutCache { 
+            lines: Vec::new(), tree: vec![0], wrap: (WrapMode::None, None),
+            anchor: ScrollAnchor::default(),
+        }
+    }
+
+    /// Returns the number of lines.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Forgets the layout of every line and sets the number of lines.
+    pub fn reset(&mut self, line_count: usize) {
+        self.lines = (0 .. line_count).map(|_| LineLayout::unknown()).collect();
+        self.rebuild();
+        self.anchor = ScrollAnchor {
+            line: cmp::min(self.anchor.line, line_count.saturating_sub(1)),
+            row: 0,
+        };
+    }
+
+    /// Sets how and at which width lines wrap, laying every line out again
+    /// if it changed. The view keeps showing the line at its top.
+    pub fn set_wrap(&mut self, mode: WrapMode, width: Option<u32>) {
+        if (mode, width) != self.wrap {
+            self.wrap = (mode, width);
+            for line in &mut self.lines {
+                line.key = None;
+            }
+            self.anchor.row = 0;
+        }
+    }
+
+    /// Makes a line be laid out again, keeping its height until it is.
+    pub fn invalidate(&mut self, line: usize) {
+        if let Some(layout) = self.lines.get_mut(line) {
+            layout.key = None;
+        }
+    }
+
+    /// Replaces the layouts of the lines an edit replaced. The view keeps
+    /// showing the line at its top, unless the edit removed it.
+    pub fn apply_edit(&mut self, edit: &LineEdit) {
+        let edit_end = edit.line + edit.removed;
+        if self.anchor.line >= edit_end {
+            self.anchor.line = self.anchor.line + edit.inserted - edit.removed;
+        } else if self.anchor.line >= edit.line + edit.inserted {
+            self.anchor = ScrollAnchor { 
+                line: edit.line + edit.inserted.saturating_sub(1), row: 0 
+            };
+        }
+        let end = cmp::min(edit.line + edit.removed, self.lines.len());
+        let start = cmp::min(edit.line, end);
+        let inserted = (0 .. edit.inserted).map(|_| LineLayout::unknown());
+        if edit.removed == edit.inserted && end - start == edit.removed {
+            // Keep the heights so that the lines after don't move yet
+            for line in start .. end {
+                self.lines[line].key = None;
+            }
+        } else {
+            self.lines.splice(start .. end, inserted);
+            self.rebuild();
+        }
+    }
+
+    /// Returns where a line wraps, if it was laid out with the same key.
+    pub fn breaks(&self, line: usize, key: &K) -> Option<&[usize]> {
+        self.lines.get(line).and_then(|layout| match layout.key {
+            Some(ref laid_out) if laid_out == key => Some(&layout.breaks[..]),
+            _ => None,
+        })
+    }
+
+    /// Stores where a line wraps. Returns whether its number of visual lines
+    /// changed, which moves the lines after it.
+    pub fn set_breaks(&mut self, line: usize, key: K, breaks: Vec<usize>)
+            -> bool {
+        let old_count = self.lines[line].visual_lines();
+        let new_count = breaks.len() + 1;
+        self.lines[line] = LineLayout { key: Some(key), breaks: breaks };
+        if line == self.anchor.line {
+            self.anchor.row = cmp::min(self.anchor.row, new_count - 1);
+        }
+        if new_count != old_count {
+            self.add(line, new_count as isize - old_count as isize);
+        }
+        new_count != old_count
+    }
+
+    /// Returns the number of visual lines a line is drawn as.
+    pub fn visual_lines(&self, line: usize) -> usize {
+        self.lines.get(line).map_or(1, |layout| layout.visual_lines())
+    }
+
+    /// Returns the number of visual lines before a line.
+    pub fn visual_line_of(&self, line: usize) -> usize {
+        let mut i = cmp::min(line, self.lines.len());
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= low_bit(i);
+        }
+        sum
+    }
+
+    /// Returns the number of visual lines of all lines.
+    pub fn total_visual_lines(&self) -> usize {
+        self.visual_line_of(self.lines.len())
+    }
+
+    /// Returns the line that a visual line belongs to and which of its visual
+    /// lines it is. Visual lines past the end are in the last line.
+    pub fn line_at_visual(&self, visual_line: usize) -> (usize, usize) {
+        if self.lines.is_empty() {
+            return (0, 0);
+        }
+        // Find the most lines that fit before the visual line
+        let mut pos = 0;
+        let mut remaining = visual_line;
+        let mut step = self.lines.len().next_power_of_two();
+        while step > 0 {
+            if pos + step <= self.lines.len() 
+                    && self.tree[pos + step] <= remaining {
+                pos += step;
+                remaining -= self.tree[pos];
+            }
+            step /= 2;
+        }
+        if pos == self.lines.len() {
+            let last = pos - 1;
+            (last, self.visual_lines(last) - 1)
+        } else {
+            (pos, remaining)
+        }
+    }
+
+    /// Returns the y offset of a line from the top of the text.
+    pub fn y_of_line(&self, line: usize, line_height: u32) -> i32 {
+        (self.visual_line_of(line) as u32 * line_height) as i32
+    }
+
+    /// Returns the line and visual line within it at a y offset from the top
+    /// of the text.
+    pub fn line_at_y(&self, y: i32, line_height: u32) -> (usize, usize) {
+        let y = if y < 0 { 0 } else { y as u32 };
+        self.line_at_visual((y / line_height) as usize)
+    }
+
+    pub fn scroll_anchor(&self) -> ScrollAnchor {
+        self.anchor
+    }
+
+    /// Returns the visual line at the top of the view.
+    pub fn scroll_top(&self) -> usize {
+        if self.lines.is_empty() {
+            return 0;
+        }
+        let line = cmp::min(self.anchor.line, self.lines.len() - 1);
+        self.visual_line_of(line) 
+            + cmp::min(self.anchor.row, self.visual_lines(line) - 1)
+    }
+
+    /// Scrolls the view so that a visual line is at its top.
+    pub fn scroll_to(&mut self, visual_line: usize) {
+        let (line, row) = self.line_at_visual(visual_line);
+        self.anchor = ScrollAnchor { line: line, row: row };
+    }
+
+    /// Scrolls the view by a number of visual lines, up when negative.
+    pub fn scroll_by(&mut self, rows: isize) {
+        let top = self.scroll_top() as isize + rows;
+        self.scroll_to(if top < 0 { 0 } else { top as usize });
+    }
+
+    /// Scrolls the view as little as possible to show a line in a view of
+    /// the given number of visual lines. Returns whether it scrolled.
+    pub fn scroll_to_line(&mut self, line: usize, view_rows: usize) -> bool {
+        let old = self.anchor;
+        let top = self.scroll_top();
+        let start = self.visual_line_of(line);
+        let end = start + self.visual_lines(line);
+        if start < top {
+            self.anchor = ScrollAnchor { line: line, row: 0 };
+        } else if end > top + view_rows {
+            // Keep the start of a line that is taller than the view in it
+            self.scroll_to(cmp::min(end.saturating_sub(view_rows), start));
+        }
+        self.anchor != old
+    }
+
+    /// Adds to the visual line count of a line.
+    fn add(&mut self, line: usize, delta: isize) {
+        let mut i = line + 1;
+        while i < self.tree.len() {
+            self.tree[i] = (self.tree[i] as isize + delta) as usize;
+            i += low_bit(i);
+        }
+    }
+
+    /// Builds the tree from the lines in O(n).
+    fn rebuild(&mut self) {
+        let n = self.lines.len();
+        self.tree = vec![0; n + 1];
+        for i in 1 .. n + 1 {
+            self.tree[i] += self.lines[i - 1].visual_lines();
+            let parent = i + low_bit(i);
+            if parent <= n {
+                let sum = self.tree[i];
+                self.tree[parent] += sum;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(counts: &[usize]) -> LayoutCache<()> {
+        let mut cache = LayoutCache::new();
+        cache.reset(counts.len());
+        for (line, &count) in counts.iter().enumerate() {
+            cache.set_breaks(line, (), (1 .. count).collect());
+        }
+        cache
+    }
+
+    #[test]
+    fn test_visual_lines() {
+        let cache = cache(&[1, 3, 2, 1]);
+        assert_eq!(cache.visual_line_of(0), 0);
+        assert_eq!(cache.visual_line_of(2), 4);
+        assert_eq!(cache.total_visual_lines(), 7);
+        assert_eq!(cache.line_at_visual(0), (0, 0));
+        assert_eq!(cache.line_at_visual(3), (1, 2));
+        assert_eq!(cache.line_at_visual(5), (2, 1));
+        assert_eq!(cache.line_at_visual(6), (3, 0));
+        assert_eq!(cache.line_at_visual(50), (3, 0));
+        assert_eq!(cache.line_at_y(25, 10), (1, 1));
+    }
+
+    #[test]
+    fn test_edits_keep_other_lines() {
+        let mut cache = cache(&[2, 1, 3]);
+        cache.apply_edit(&LineEdit { line: 1, removed: 1, inserted: 1 });
+        assert!(cache.breaks(0, &()).is_some());
+        assert!(cache.breaks(1, &()).is_none());
+        assert_eq!(cache.total_visual_lines(), 6);
+        // Splitting a line adds an unknown line before the one after it
+        cache.apply_edit(&LineEdit { line: 0, removed: 1, inserted: 2 });
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.breaks(3, &()), Some(&[1, 2][..]));
+        assert_eq!(cache.visual_line_of(3), 3);
+        assert!(cache.set_breaks(1, (), vec![4]));
+        assert_eq!(cache.line_at_visual(4), (3, 0));
+    }
+
+    #[test]
+    fn test_scroll_anchor() {
+        let mut cache = cache(&[1, 3, 2, 1]);
+        assert!(cache.scroll_to_line(2, 3));
+        assert_eq!(cache.scroll_anchor(), ScrollAnchor { line: 1, row: 2 });
+        assert!(cache.scroll_to_line(3, 3));
+        assert_eq!(cache.scroll_top(), 4);
+        assert!(! cache.scroll_to_line(2, 3));
+        cache.scroll_by(-2);
+        assert_eq!(cache.scroll_anchor(), ScrollAnchor { line: 1, row: 1 });
+        // Lines added above the anchor don't move the view
+        cache.apply_edit(&LineEdit { line: 0, removed: 1, inserted: 3 });
+        assert_eq!(cache.scroll_anchor(), ScrollAnchor { line: 3, row: 1 });
+        // The line at the top stays there when it is wrapped again
+        cache.set_breaks(3, (), vec![]);
+        assert_eq!(cache.scroll_anchor(), ScrollAnchor { line: 3, row: 0 });
+        cache.set_wrap(WrapMode::Viewport, Some(100));
+        assert_eq!(cache.scroll_top(), 3);
+    }
+}
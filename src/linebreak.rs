@@ -0,0 +1,341 @@
+//! Finding where lines may be broken, by a hand-written subset of the Unicode
+//! line breaking algorithm (UAX #14). This is not a full implementation.
+//!
+//! The class table only lists the characters in `break_class`; everything
+//! else is a letter (AL). The classes AL, BA, BB, B2, CL, CP, CM, EB, EM, EX,
+//! GL, HY, ID, IN, IS, NS, NU, OP, PO, PR, QU, RI, SP, SY, WJ, ZW and ZWJ are
+//! covered. Of the rest:
+//!
+//! - BK, CR, LF and NL never occur, because text is split into lines first.
+//! - AI, CB, CJ, HL, SA and XX are treated as letters, so scripts that need
+//!   a dictionary, like Thai, are broken like letters.
+//! - Hangul (H2, H3, JL, JV, JT) is treated as ideographs.
+//!
+//! The rules covered are LB7 to LB19, LB21 to LB24, a simplified LB25 (pairs
+//! of characters in a number, not the full pattern), LB28 to LB30b and LB31.
+//! LB20, LB21a, LB21b, LB26, LB27 and LB28a are not, nor are the newer
+//! refinements of LB15 and LB19 for quotation marks.
+
+/// The line breaking class of a character.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakClass {
+    /// Letters and symbols.
+    AL,
+    /// Text after which a line may break, like tabs.
+    BA,
+    /// Text before which a line may break.
+    BB,
+    /// The em dash, which breaks around but not between.
+    B2,
+    /// Closing punctuation.
+    CL,
+    /// Closing parentheses.
+    CP,
+    /// Combining marks, which take the class of what they are attached to.
+    CM,
+    /// Emoji that can have a skin tone.
+    EB,
+    /// Skin tone modifiers.
+    EM,
+    /// Exclamation and question marks.
+    EX,
+    /// Non-breaking ("glue") characters.
+    GL,
+    /// The hyphen-minus.
+    HY,
+    /// Ideographs, which lines can break between.
+    ID,
+    /// Ellipses.
+    IN,
+    /// Infix separators like commas and periods.
+    IS,
+    /// Characters that can't start a line, like small kana.
+    NS,
+    /// Digits.
+    NU,
+    /// Opening punctuation.
+    OP,
+    /// Postfixes of numbers, like percent signs.
+    PO,
+    /// Prefixes of numbers, like currency signs.
+    PR,
+    /// Quotation marks.
+    QU,
+    /// Regional indicators, which make flags in pairs.
+    RI,
+    /// Spaces.
+    SP,
+    /// Slashes.
+    SY,
+    /// The word joiner.
+    WJ,
+    /// The zero width space.
+    ZW,
+    /// The zero width joiner.
+    ZWJ,
+}
+
+use self::BreakClass::*;
+
+/// Returns the line breaking class of a character.
+pub fn break_class(ch: char) -> BreakClass {
+    match ch {
+        ' ' => SP,
+        '\t' | '|' | '\u{AD}' | '\u{5BE}' | '\u{1680}' | '\u{2000}' ..= '\u{2006}'
+            | '\u{2008}' ..= '\u{200A}' | '\u{2010}' | '\u{2012}' | '\u{2013}'
+            | '\u{205F}' | '\u{3000}' => BA,
+        '\u{B4}' | '\u{2C8}' | '\u{2CC}' | '\u{2DF}' => BB,
+        '\u{2014}' => B2,
+        '-' => HY,
+        '\u{A0}' | '\u{34F}' | '\u{2007}' | '\u{2011}' | '\u{202F}' => GL,
+        '\u{2060}' | '\u{FEFF}' => WJ,
+        '\u{200B}' => ZW,
+        '\u{200D}' => ZWJ,
+        '\u{2024}' ..= '\u{2026}' | '\u{22EF}' | '\u{FE19}' => IN,
+        '(' | '[' | '{' | '\u{A1}' | '\u{BF}' | '\u{2045}' | '\u{207D}'
+            | '\u{208D}' | '\u{3008}' | '\u{300A}' | '\u{300C}' | '\u{300E}'
+            | '\u{3010}' | '\u{3014}' | '\u{3016}' | '\u{3018}' | '\u{301A}'
+            | '\u{301D}' | '\u{FF08}' | '\u{FF3B}' | '\u{FF5B}' | '\u{FF5F}'
+            | '\u{FF62}' => OP,
+        ')' | ']' => CP,
+        '}' | '\u{2046}' | '\u{207E}' | '\u{208E}' | '\u{3001}' | '\u{3002}'
+            | '\u{3009}' | '\u{300B}' | '\u{300D}' | '\u{300F}' | '\u{3011}'
+            | '\u{3015}' | '\u{3017}' | '\u{3019}' | '\u{301B}' | '\u{301E}'
+            | '\u{301F}' | '\u{FF09}' | '\u{FF0C}' | '\u{FF0E}' | '\u{FF3D}'
+            | '\u{FF5D}' | '\u{FF60}' | '\u{FF61}' | '\u{FF63}' | '\u{FF64}' => CL,
+        '!' | '?' | '\u{FF01}' | '\u{FF1F}' => EX,
+        ',' | '.' | ':' | ';' | '\u{37E}' | '\u{589}' | '\u{60C}' | '\u{60D}'
+            | '\u{2044}' | '\u{FE10}' | '\u{FE13}' | '\u{FE14}' => IS,
+        '/' => SY,
+        '"' | '\'' | '\u{AB}' | '\u{BB}' | '\u{2018}' ..= '\u{201F}'
+            | '\u{2039}' | '\u{203A}' | '\u{275B}' ..= '\u{2760}' => QU,
+        '$' | '+' | '\\' | '\u{A3}' | '\u{A5}' | '\u{B1}' | '\u{2116}'
+            | '\u{2212}' | '\u{2213}' | '\u{20A0}' ..= '\u{20CF}' => PR,
+        '%' | '\u{A2}' | '\u{B0}' | '\u{2030}' ..= '\u{2037}' | '\u{2103}'
+            | '\u{2109}' | '\u{FF05}' | '\u{FFE0}' => PO,
+        '0' ..= '9' | '\u{660}' ..= '\u{669}' | '\u{6F0}' ..= '\u{6F9}'
+            | '\u{966}' ..= '\u{96F}' => NU,
+        // Small kana, iteration marks and the prolonged sound mark
+        '\u{203C}' | '\u{2047}' ..= '\u{2049}' | '\u{3005}' | '\u{301C}'
+            | '\u{303B}' | '\u{3041}' | '\u{3043}' | '\u{3045}' | '\u{3047}'
+            | '\u{3049}' | '\u{3063}' | '\u{3083}' | '\u{3085}' | '\u{3087}'
+            | '\u{308E}' | '\u{3095}' | '\u{3096}' | '\u{309D}' | '\u{309E}'
+            | '\u{30A0}' | '\u{30A1}' | '\u{30A3}' | '\u{30A5}' | '\u{30A7}'
+            | '\u{30A9}' | '\u{30C3}' | '\u{30E3}' | '\u{30E5}' | '\u{30E7}'
+            | '\u{30EE}' | '\u{30F5}' | '\u{30F6}' | '\u{30FB}' ..= '\u{30FE}'
+            | '\u{31F0}' ..= '\u{31FF}' | '\u{FF1A}' | '\u{FF1B}' | '\u{FF65}'
+            | '\u{FF67}' ..= '\u{FF70}' | '\u{FF9E}' | '\u{FF9F}' => NS,
+        '\u{300}' ..= '\u{36F}' | '\u{483}' ..= '\u{489}' | '\u{591}' ..= '\u{5BD}'
+            | '\u{610}' ..= '\u{61A}' | '\u{64B}' ..= '\u{65F}' | '\u{670}'
+            | '\u{6D6}' ..= '\u{6DC}' | '\u{900}' ..= '\u{903}'
+            | '\u{93A}' ..= '\u{94F}' | '\u{951}' ..= '\u{957}'
+            | '\u{962}' | '\u{963}' | '\u{1AB0}' ..= '\u{1AFF}'
+            | '\u{1DC0}' ..= '\u{1DFF}' | '\u{20D0}' ..= '\u{20FF}'
+            | '\u{302A}' ..= '\u{302F}' | '\u{3099}' | '\u{309A}'
+            | '\u{FE00}' ..= '\u{FE0F}' | '\u{FE20}' ..= '\u{FE2F}'
+            | '\u{E0020}' ..= '\u{E007F}' | '\u{E0100}' ..= '\u{E01EF}' => CM,
+        '\u{1F1E6}' ..= '\u{1F1FF}' => RI,
+        '\u{1F3FB}' ..= '\u{1F3FF}' => EM,
+        // Pictographs are all treated as able to take a skin tone
+        '\u{261D}' | '\u{26F9}' | '\u{270A}' ..= '\u{270D}'
+            | '\u{1F300}' ..= '\u{1F3FA}' | '\u{1F400}' ..= '\u{1F64F}'
+            | '\u{1F680}' ..= '\u{1F6FF}' | '\u{1F900}' ..= '\u{1F9FF}'
+            | '\u{1FA70}' ..= '\u{1FAFF}' => EB,
+        '\u{231A}' | '\u{231B}' | '\u{23F0}' ..= '\u{23F3}'
+            | '\u{2600}' ..= '\u{2603}' | '\u{2614}' | '\u{2615}'
+            | '\u{2618}' ..= '\u{261C}' | '\u{261E}' ..= '\u{261F}'
+            | '\u{2639}' ..= '\u{263B}' | '\u{2668}' | '\u{267F}'
+            | '\u{26BD}' ..= '\u{26C8}' | '\u{26CD}' | '\u{26CF}' ..= '\u{26D1}'
+            | '\u{26D3}' ..= '\u{26D4}' | '\u{26D8}' ..= '\u{26D9}'
+            | '\u{26DC}' | '\u{26DF}' ..= '\u{26E1}' | '\u{26EA}'
+            | '\u{26F1}' ..= '\u{26F5}' | '\u{26F7}' ..= '\u{26F8}'
+            | '\u{26FA}' ..= '\u{26FF}' | '\u{2705}' | '\u{2708}' ..= '\u{2709}'
+            | '\u{2764}'
+            | '\u{2E80}' ..= '\u{2FFF}' | '\u{3003}' | '\u{3004}'
+            | '\u{3006}' | '\u{3007}' | '\u{3012}' | '\u{3013}'
+            | '\u{3020}' ..= '\u{3029}' | '\u{3030}' ..= '\u{303A}'
+            | '\u{303C}' ..= '\u{303F}' | '\u{3040}' ..= '\u{309F}'
+            | '\u{30A0}' ..= '\u{30FF}' | '\u{3100}' ..= '\u{31EF}'
+            | '\u{3200}' ..= '\u{4DBF}' | '\u{4E00}' ..= '\u{9FFF}'
+            | '\u{A000}' ..= '\u{A4CF}' | '\u{AC00}' ..= '\u{D7A3}'
+            | '\u{F900}' ..= '\u{FAFF}' | '\u{FE30}' ..= '\u{FE4F}'
+            | '\u{FF02}' ..= '\u{FF60}' | '\u{FFE1}' ..= '\u{FFE6}'
+            | '\u{1F000}' ..= '\u{1F1E5}' | '\u{1F200}' ..= '\u{1F2FF}'
+            | '\u{20000}' ..= '\u{2FFFD}' | '\u{30000}' ..= '\u{3FFFD}' => ID,
+        _ => AL,
+    }
+}
+
+/// Returns whether a character attaches to the one before it, like
+/// combining marks do.
+fn attaches(class: BreakClass) -> bool {
+    class == CM || class == ZWJ
+}
+
+/// Returns whether a line may break between two characters, by the pair rules
+/// of the algorithm. `before` is the class of the last character before any
+/// spaces in front of the second character, and `regional` the number of
+/// regional indicators in a row that end with the first one.
+fn pair_breaks(a: BreakClass, before: BreakClass, b: BreakClass, b_char: char,
+        regional: usize) -> bool {
+    let wide = b_char >= '\u{2E80}';
+    if b == SP || b == ZW {
+        false
+    } else if before == ZW {
+        true
+    } else if a == WJ || b == WJ || a == GL {
+        false
+    } else if b == GL && a != SP && a != BA && a != HY {
+        false
+    } else if b == CL || b == CP || b == EX || b == IS || b == SY {
+        false
+    } else if before == OP {
+        false
+    } else if before == QU && b == OP {
+        false
+    } else if (before == CL || before == CP) && b == NS {
+        false
+    } else if before == B2 && b == B2 {
+        false
+    } else if a == SP {
+        true
+    } else if a == QU || b == QU {
+        false
+    } else if b == BA || b == HY || b == NS || b == IN || a == BB {
+        false
+    } else if (a == AL && b == NU) || (a == NU && b == AL) {
+        false
+    } else if a == PR && (b == ID || b == EB || b == EM) {
+        false
+    } else if (a == ID || a == EB || a == EM) && b == PO {
+        false
+    } else if (a == PR || a == PO) && b == AL || a == AL && (b == PR || b == PO) {
+        false
+    } else if b == NU && (a == PR || a == PO || a == HY || a == IS || a == SY
+            || a == NU) {
+        false
+    } else if a == NU && (b == PO || b == PR) {
+        false
+    } else if a == AL && b == AL || a == IS && b == AL {
+        false
+    } else if (a == AL || a == NU) && b == OP && ! wide {
+        false
+    } else if a == CP && (b == AL || b == NU) {
+        false
+    } else if a == RI && b == RI && regional % 2 == 1 {
+        false
+    } else if a == EB && b == EM {
+        false
+    } else {
+        true
+    }
+}
+
+/// Returns the byte indices in a line of text where it may be broken, which
+/// are the starts of the characters that could begin a new line.
+pub fn break_opportunities(text: &str) -> Vec<usize> {
+    let mut breaks = Vec::new();
+    let mut chars = text.char_indices();
+    let first = match chars.next() {
+        Some((_, ch)) => break_class(ch),
+        None => return breaks,
+    };
+    // Combining marks take the class of the character they are attached to,
+    // or are letters when there is none
+    let mut prev = if attaches(first) { AL } else { first };
+    let mut prev_raw = first;
+    let mut before = prev;
+    let mut regional = if prev == RI { 1 } else { 0 };
+    for (index, ch) in chars {
+        let raw = break_class(ch);
+        let attached = attaches(raw) && prev != SP && prev != ZW;
+        let class = if attaches(raw) { AL } else { raw };
+        let breaks_here = if prev_raw == ZWJ || attached {
+            false
+        } else {
+            pair_breaks(prev, before, class, ch, regional)
+        };
+        if breaks_here {
+            breaks.push(index);
+        }
+        if ! attached {
+            regional = if class == RI && prev == RI { regional + 1 }
+                else if class == RI { 1 }
+                else { 0 };
+            prev = class;
+        }
+        prev_raw = raw;
+        if prev != SP {
+            before = prev;
+        }
+    }
+    breaks
+}
+
+/// Returns whether two characters are drawn together, so that a line can't
+/// be broken between them even when breaking between any characters.
+pub fn joins(prev: char, next: char) -> bool {
+    let (a, b) = (break_class(prev), break_class(next));
+    attaches(b) || a == ZWJ || (a == EB && b == EM) || (a == RI && b == RI)
+}
+
+/// Returns whether a character is a space that can hang past the end of a
+/// line instead of being moved to the next one.
+pub fn hangs(ch: char) -> bool {
+    ch.is_whitespace() && break_class(ch) != GL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(text: &str) -> Vec<&str> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for index in break_opportunities(text) {
+            pieces.push(&text[start..index]);
+            start = index;
+        }
+        pieces.push(&text[start..]);
+        pieces
+    }
+
+    #[test]
+    fn test_spaces_and_punctuation() {
+        assert_eq!(pieces("Hello, world! (yes)"),
+            vec!["Hello, ", "world! ", "(yes)"]);
+        assert_eq!(pieces("a  \"quoted\" word"),
+            vec!["a  ", "\"quoted\" ", "word"]);
+    }
+
+    #[test]
+    fn test_hyphens_and_slashes() {
+        assert_eq!(pieces("well-known"), vec!["well-", "known"]);
+        assert_eq!(pieces("from -5 to 10-20"), vec!["from ", "-5 ", "to ", "10-20"]);
+        assert_eq!(pieces("http://example.org/a/b"),
+            vec!["http://", "example.org/", "a/", "b"]);
+    }
+
+    #[test]
+    fn test_no_break_at_non_breaking_space() {
+        assert_eq!(pieces("10\u{A0}kg of"), vec!["10\u{A0}kg ", "of"]);
+        assert_eq!(pieces("a\u{2060}b c"), vec!["a\u{2060}b ", "c"]);
+    }
+
+    #[test]
+    fn test_ideographs() {
+        assert_eq!(pieces("日本語。"), vec!["日", "本", "語。"]);
+        // Small kana and closing brackets don't start lines
+        assert_eq!(pieces("「ちょっと」"), vec!["「ちょっ", "と」"]);
+        assert_eq!(pieces("abc日本"), vec!["abc", "日", "本"]);
+    }
+
+    #[test]
+    fn test_emoji() {
+        assert_eq!(pieces("👍🏽👍"), vec!["👍🏽", "👍"]);
+        assert_eq!(pieces("👩\u{200D}👩\u{200D}👧!"),
+            vec!["👩\u{200D}👩\u{200D}👧!"]);
+        assert_eq!(pieces("🇳🇴🇸🇪"), vec!["🇳🇴", "🇸🇪"]);
+        assert!(joins('e', '\u{301}') && ! joins('a', 'b'));
+    }
+}
@@ -1,15 +1,105 @@
 #![allow(unused)]
 extern crate sdl2;
 extern crate sdl2_ttf;
+extern crate sdl2_image;
+extern crate regex;
+extern crate toml;
 
 mod common;
+mod cache;
+mod atlas;
 mod cursor;
+mod attributes;
 mod textfield;
+mod measure;
+mod damage;
+mod caret;
+mod linebreak;
 mod layout;
+mod layout_cache;
+mod highlight;
+mod fonts;
+mod config;
+mod clipboard;
+mod registers;
+mod editor;
+mod commands;
+mod keymap;
 mod render_textfield;
+mod snapshot;
 
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
 use cursor::Cursor;
 use textfield::Textfield;
+use editor::Editor;
+use highlight::{GrammarSet, Highlighter};
+use config::{Config, ConfigWatcher, Overrides};
+
+const USAGE: &'static str = "\
+Usage: editor [options] [file]
+
+Options:
+    --config <path>       The config file to use instead of the default
+    --font <name|path>    The font family or font file to use
+    --font-size <size>    The font size in points
+    --screenshot <path>   Save the text as a PNG without opening a window";
+
+/// The parsed command-line arguments.
+struct Args {
+    file: Option<String>,
+    config: Option<String>,
+    font_family: Option<String>,
+    font_size: Option<u16>,
+    screenshot: Option<String>,
+}
+
+/// Parses the command-line arguments.
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args { 
+        file: None, config: None, font_family: None, font_size: None,
+        screenshot: None,
+    };
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = iter.next()
+                    .ok_or(String::from("--config needs a value"))?;
+                args.config = Some(path);
+            },
+            "--font" => {
+                let family = iter.next()
+                    .ok_or(String::from("--font needs a value"))?;
+                args.font_family = Some(family);
+            },
+            "--font-size" => {
+                let size = iter.next()
+                    .ok_or(String::from("--font-size needs a value"))?;
+                args.font_size = Some(size.parse()
+                    .map_err(|_| format!("Invalid font size: '{}'", size))?);
+            },
+            "--screenshot" => {
+                let path = iter.next()
+                    .ok_or(String::from("--screenshot needs a value"))?;
+                args.screenshot = Some(path);
+            },
+            "-h" | "--help" => {
+                return Err(String::new());
+            },
+            _ if arg.starts_with("--") => {
+                return Err(format!("Unknown option: '{}'", arg));
+            },
+            _ => {
+                args.file = Some(arg);
+            },
+        }
+    }
+    Ok(args)
+}
 
 fn main() {
     let text = "\
@@ -19,7 +109,69 @@ fn main() {
     Thisisaverylongsinglewordtoshowthatthisisalsowrappedcorrectly\n\
     This is a line!";
     
-    let mut field = Textfield::new(text);
+    let args = match parse_args() {
+        Ok(args) => args,
+        // Asking for help isn't an error
+        Err(ref msg) if msg.is_empty() => {
+            println!("{}", USAGE);
+            process::exit(0);
+        },
+        Err(msg) => {
+            println!("{}\n", msg);
+            println!("{}", USAGE);
+            process::exit(1);
+        },
+    };
+    
+    let overrides = Overrides { 
+        font_family: args.font_family, font_size: args.font_size,
+    };
+    let watcher = args.config.map(PathBuf::from).or_else(config::default_path)
+        .map(|path| ConfigWatcher::new(path, overrides.clone()));
+    let config = match watcher.as_ref().map(|w| w.load()) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            println!("Invalid config: {}", e);
+            process::exit(1);
+        },
+        None => {
+            let mut config = Config::default();
+            overrides.apply(&mut config);
+            config
+        },
+    };
+    
+    let grammars = GrammarSet::builtin();
+    let (field, mut highlighter) = match args.file {
+        Some(path) => {
+            let path = Path::new(&path);
+            let mut contents = String::new();
+            File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+                .expect("Could not read file");
+            let highlighter = grammars.for_path(path)
+                .map(|grammar| Highlighter::new(grammar.clone()));
+            (Textfield::new(&contents), highlighter)
+        },
+        None => (Textfield::new(text), None),
+    };
+    
+    if let Some(path) = args.screenshot {
+        let path = Path::new(&path);
+        if let Some(ref mut hl) = highlighter {
+            hl.update(&field.lines);
+        }
+        let result = snapshot::export_png(&field, &config, 
+            highlighter.as_ref(), path);
+        if let Err(e) = result {
+            println!("Could not save the screenshot: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
     
-    render_textfield::main(&mut field);
+    let mut editor = Editor::new(field, config.tabs);
+    editor.wrap = config.wrap;
+    editor.wrap_style = config.wrap_style;
+    editor.highlighter = highlighter;
+    render_textfield::main(&mut editor, config, watcher);
 }
@@ -0,0 +1,501 @@
+//! Measuring text for layout.
+extern crate sdl2_ttf;
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::rc::Rc;
+use sdl2_ttf::Font;
+
+/// Measures text in pixels.
+pub trait TextMeasurer {
+    /// Returns the width of a string.
+    fn width(&self, text: &str) -> u32;
+
+    /// Returns the width of text that starts `offset` bytes into the line
+    /// being laid out. Measurers that draw parts of a line in other fonts
+    /// tell from the offset which parts the text covers.
+    fn width_at(&self, text: &str, _offset: usize) -> u32 {
+        self.width(text)
+    }
+
+    /// Returns the width of a string drawn in the bold or italic variant of
+    /// the font. Measurers without variants measure it like other text.
+    fn styled_width(&self, text: &str, _bold: bool, _italic: bool) -> u32 {
+        self.width(text)
+    }
+
+    /// Returns how far a single glyph advances the pen.
+    fn advance(&self, ch: char) -> u32;
+
+    /// Returns the distance between the baselines of two lines.
+    fn line_height(&self) -> u32;
+
+    /// Returns the distance from the baseline to the top of the line.
+    fn ascent(&self) -> i32;
+
+    /// Returns the distance from the baseline to the bottom of the line. This
+    /// is negative, like in SDL_ttf.
+    fn descent(&self) -> i32;
+
+    /// Returns how much closer (when negative) or further apart two glyphs
+    /// are drawn than their advances say.
+    fn kerning(&self, left: char, right: char) -> i32 {
+        let mut pair = String::new();
+        pair.push(left);
+        pair.push(right);
+        self.width(&pair) as i32 - self.advance(left) as i32
+            - self.advance(right) as i32
+    }
+
+    /// Returns an upper bound on the advance of any glyph, if one is known,
+    /// so that short text doesn't need to be measured to know it fits.
+    fn max_advance(&self) -> Option<u32> {
+        None
+    }
+
+    /// Returns the distance between tab stops, if tabs advance to the next
+    /// one instead of being measured like other characters.
+    fn tab_stop(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl<'a, M: TextMeasurer + ?Sized> TextMeasurer for &'a M {
+    fn width(&self, text: &str) -> u32 { (**self).width(text) }
+    fn width_at(&self, text: &str, offset: usize) -> u32 {
+        (**self).width_at(text, offset)
+    }
+    fn styled_width(&self, text: &str, bold: bool, italic: bool) -> u32 {
+        (**self).styled_width(text, bold, italic)
+    }
+    fn advance(&self, ch: char) -> u32 { (**self).advance(ch) }
+    fn line_height(&self) -> u32 { (**self).line_height() }
+    fn ascent(&self) -> i32 { (**self).ascent() }
+    fn descent(&self) -> i32 { (**self).descent() }
+    fn kerning(&self, left: char, right: char) -> i32 {
+        (**self).kerning(left, right)
+    }
+    fn max_advance(&self) -> Option<u32> { (**self).max_advance() }
+    fn tab_stop(&self) -> Option<u32> { (**self).tab_stop() }
+}
+
+/// Measures text rendered with an SDL_ttf font.
+#[derive(Clone)]
+pub struct FontMeasurer {
+    pub font: Rc<Font>,
+}
+
+impl FontMeasurer {
+    pub fn new(font: Rc<Font>) -> FontMeasurer {
+        FontMeasurer { font: font }
+    }
+}
+
+impl TextMeasurer for FontMeasurer {
+    fn width(&self, text: &str) -> u32 {
+        let (width, _) = self.font.size_of(text).expect("Could not get font size");
+        width
+    }
+
+    fn advance(&self, ch: char) -> u32 {
+        let (width, _) = self.font.size_of_char(ch)
+            .expect("Could not get glyph size");
+        width
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.recommended_line_height() as u32
+    }
+
+    fn ascent(&self) -> i32 {
+        self.font.ascent()
+    }
+
+    fn descent(&self) -> i32 {
+        self.font.descent()
+    }
+}
+
+/// Measures every character as the same width, for tests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedWidthMeasurer {
+    pub advance: u32,
+    pub line_height: u32,
+}
+
+impl FixedWidthMeasurer {
+    pub fn new(advance: u32, line_height: u32) -> FixedWidthMeasurer {
+        FixedWidthMeasurer { advance: advance, line_height: line_height }
+    }
+}
+
+impl TextMeasurer for FixedWidthMeasurer {
+    fn width(&self, text: &str) -> u32 {
+        text.chars().count() as u32 * self.advance
+    }
+
+    fn advance(&self, _: char) -> u32 {
+        self.advance
+    }
+
+    fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    fn ascent(&self) -> i32 {
+        (self.line_height * 4 / 5) as i32
+    }
+
+    fn descent(&self) -> i32 {
+        self.ascent() - self.line_height as i32
+    }
+
+    fn kerning(&self, _: char, _: char) -> i32 {
+        0
+    }
+
+    fn max_advance(&self) -> Option<u32> {
+        Some(self.advance)
+    }
+}
+
+/// The number of widths a cached measurer keeps before starting over.
+const DEFAULT_CACHE_CAPACITY: usize = 4096;
+
+/// Remembers the measurements of another measurer. Only use this for
+/// measurers where the width only depends on the text.
+pub struct CachedMeasurer<M> {
+    inner: M,
+    capacity: usize,
+    widths: RefCell<HashMap<String, u32>>,
+    advances: RefCell<HashMap<char, u32>>,
+}
+
+impl<M: TextMeasurer> CachedMeasurer<M> {
+    pub fn new(inner: M) -> CachedMeasurer<M> {
+        CachedMeasurer::with_capacity(inner, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// Creates a cache that is cleared when it has more than `capacity`
+    /// widths.
+    pub fn with_capacity(inner: M, capacity: usize) -> CachedMeasurer<M> {
+        CachedMeasurer {
+            inner: inner, capacity: capacity,
+            widths: RefCell::new(HashMap::new()),
+            advances: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+}
+
+impl<M: TextMeasurer + Clone> Clone for CachedMeasurer<M> {
+    fn clone(&self) -> CachedMeasurer<M> {
+        CachedMeasurer {
+            inner: self.inner.clone(), capacity: self.capacity,
+            widths: self.widths.clone(), advances: self.advances.clone(),
+        }
+    }
+}
+
+impl<M: TextMeasurer> TextMeasurer for CachedMeasurer<M> {
+    fn width(&self, text: &str) -> u32 {
+        if let Some(&width) = self.widths.borrow().get(text) {
+            return width;
+        }
+        let width = self.inner.width(text);
+        let mut widths = self.widths.borrow_mut();
+        if widths.len() >= self.capacity {
+            widths.clear();
+        }
+        widths.insert(String::from(text), width);
+        width
+    }
+
+    fn advance(&self, ch: char) -> u32 {
+        let inner = &self.inner;
+        *self.advances.borrow_mut().entry(ch)
+            .or_insert_with(|| inner.advance(ch))
+    }
+
+    fn line_height(&self) -> u32 {
+        self.inner.line_height()
+    }
+
+    fn ascent(&self) -> i32 {
+        self.inner.ascent()
+    }
+
+    fn descent(&self) -> i32 {
+        self.inner.descent()
+    }
+
+    fn max_advance(&self) -> Option<u32> {
+        self.inner.max_advance()
+    }
+
+    fn tab_stop(&self) -> Option<u32> {
+        self.inner.tab_stop()
+    }
+}
+
+/// Measures text with another measurer, with tabs advancing to tab stops.
+#[derive(Clone)]
+pub struct TabbedMeasurer<M> {
+    pub inner: M,
+    /// The distance between tab stops.
+    pub tab_stop: u32,
+}
+
+impl<M: TextMeasurer> TabbedMeasurer<M> {
+    pub fn new(inner: M, tab_stop: u32) -> TabbedMeasurer<M> {
+        TabbedMeasurer { inner: inner, tab_stop: tab_stop }
+    }
+}
+
+impl<M: TextMeasurer> TextMeasurer for TabbedMeasurer<M> {
+    fn width(&self, text: &str) -> u32 { 
+        tabbed_width(text, self.tab_stop, |piece, _| self.inner.width(piece))
+    }
+    fn width_at(&self, text: &str, offset: usize) -> u32 {
+        tabbed_width(text, self.tab_stop, |piece, start| {
+            self.inner.width_at(piece, offset + start)
+        })
+    }
+    fn styled_width(&self, text: &str, bold: bool, italic: bool) -> u32 {
+        self.inner.styled_width(text, bold, italic)
+    }
+    fn advance(&self, ch: char) -> u32 { self.inner.advance(ch) }
+    fn line_height(&self) -> u32 { self.inner.line_height() }
+    fn ascent(&self) -> i32 { self.inner.ascent() }
+    fn descent(&self) -> i32 { self.inner.descent() }
+    fn kerning(&self, left: char, right: char) -> i32 {
+        self.inner.kerning(left, right)
+    }
+    fn max_advance(&self) -> Option<u32> { self.inner.max_advance() }
+    fn tab_stop(&self) -> Option<u32> { Some(self.tab_stop) }
+}
+
+/// Measures text with its tabs advancing to the next multiple of `tab_stop`
+/// from the start of the text. The text between tabs is measured with
+/// `measure`, which is given each piece, which can be empty, and the byte 
+/// index it starts at.
+pub fn tabbed_width<F>(text: &str, tab_stop: u32, measure: F) -> u32 
+        where F: Fn(&str, usize) -> u32 {
+    if tab_stop == 0 {
+        return measure(text, 0);
+    }
+    let mut x = 0;
+    let mut start = 0;
+    for (index, _) in text.match_indices('\t') {
+        x += measure(&text[start..index], start);
+        x = (x / tab_stop + 1) * tab_stop;
+        start = index + 1;
+    }
+    x + measure(&text[start..], start)
+}
+
+/// A byte range of a line that is drawn with a bold or italic font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontRun {
+    pub start: usize,
+    pub end: usize,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// Measures the bytes `start..end` of a line using the fonts of the runs
+/// they overlap.
+pub fn styled_width<M: TextMeasurer + ?Sized>(line: &str, runs: &[FontRun], 
+        start: usize, end: usize, measurer: &M) -> u32 {
+    if start == end {
+        return 0;
+    } else if runs.is_empty() {
+        return measurer.width(&line[start..end]);
+    }
+    let mut pos = start;
+    let mut width = 0;
+    for run in runs {
+        if run.end <= pos || run.start >= end {
+            continue;
+        }
+        if run.start > pos {
+            width += measurer.width(&line[pos..run.start]);
+            pos = run.start;
+        }
+        let run_end = cmp::min(run.end, end);
+        width += measurer.styled_width(&line[pos..run_end], run.bold, 
+            run.italic);
+        pos = run_end;
+    }
+    if pos < end {
+        width += measurer.width(&line[pos..end]);
+    }
+    width
+}
+
+/// Measures slices of a line with the fonts of the runs they overlap, the
+/// way the line is drawn, with tabs advancing to the tab stops of the 
+/// measurer. Text measured without an offset is measured with the regular
+/// font.
+pub struct LineMeasurer<'a, M: 'a + ?Sized> {
+    pub line: &'a str,
+    pub runs: &'a [FontRun],
+    pub measurer: &'a M,
+    /// Text drawn in front of a byte index of the line without being part of
+    /// it, like an input method composition, as the index and its width.
+    pub inserted: Option<(usize, u32)>,
+    /// A tighter upper bound on the advances than the measurer knows.
+    pub max_advance: Option<u32>,
+}
+
+impl<'a, M: TextMeasurer + ?Sized> TextMeasurer for LineMeasurer<'a, M> {
+    fn width(&self, text: &str) -> u32 { self.measurer.width(text) }
+    fn width_at(&self, text: &str, offset: usize) -> u32 {
+        let end = offset + text.len();
+        // Inserted text goes with the text after it, or the end of the line
+        let inserted = self.inserted.filter(|&(at, _)| {
+            offset <= at && (at < end || end == self.line.len())
+        });
+        let tab_stop = self.measurer.tab_stop().unwrap_or(0);
+        tabbed_width(text, tab_stop, |piece, start| {
+            let (start, end) = (offset + start, offset + start + piece.len());
+            let width = styled_width(self.line, self.runs, start, end, 
+                self.measurer);
+            // Inserted text pushes the tabs after it along
+            match inserted {
+                Some((at, inserted)) if start <= at && at <= end => {
+                    width + inserted
+                },
+                _ => width,
+            }
+        })
+    }
+    fn styled_width(&self, text: &str, bold: bool, italic: bool) -> u32 {
+        self.measurer.styled_width(text, bold, italic)
+    }
+    fn advance(&self, ch: char) -> u32 { self.measurer.advance(ch) }
+    fn line_height(&self) -> u32 { self.measurer.line_height() }
+    fn ascent(&self) -> i32 { self.measurer.ascent() }
+    fn descent(&self) -> i32 { self.measurer.descent() }
+    fn kerning(&self, left: char, right: char) -> i32 {
+        self.measurer.kerning(left, right)
+    }
+    fn max_advance(&self) -> Option<u32> { 
+        self.max_advance.or(self.measurer.max_advance())
+    }
+    fn tab_stop(&self) -> Option<u32> { self.measurer.tab_stop() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Counts how often text is measured.
+    struct Counting {
+        calls: Cell<usize>,
+    }
+
+    impl TextMeasurer for Counting {
+        fn width(&self, text: &str) -> u32 {
+            self.calls.set(self.calls.get() + 1);
+            text.len() as u32 * 2
+        }
+        fn advance(&self, _: char) -> u32 { 2 }
+        fn line_height(&self) -> u32 { 10 }
+        fn ascent(&self) -> i32 { 8 }
+        fn descent(&self) -> i32 { -2 }
+    }
+
+    #[test]
+    fn test_fixed_width() {
+        let m = FixedWidthMeasurer::new(3, 10);
+        assert_eq!(m.width("日本"), 6);
+        assert_eq!(m.ascent() - m.descent(), 10);
+        assert_eq!(m.kerning('A', 'V'), 0);
+    }
+
+    #[test]
+    fn test_default_kerning() {
+        let m = Counting { calls: Cell::new(0) };
+        assert_eq!(m.kerning('a', 'b'), 0);
+    }
+
+    /// Measures bold text wider than regular text.
+    struct Bold;
+
+    impl TextMeasurer for Bold {
+        fn width(&self, text: &str) -> u32 { text.len() as u32 * 2 }
+        fn styled_width(&self, text: &str, bold: bool, _: bool) -> u32 {
+            text.len() as u32 * if bold { 3 } else { 2 }
+        }
+        fn advance(&self, _: char) -> u32 { 2 }
+        fn line_height(&self) -> u32 { 10 }
+        fn ascent(&self) -> i32 { 8 }
+        fn descent(&self) -> i32 { -2 }
+    }
+
+    /// Measures like `Bold`, with tab stops every 8 pixels.
+    struct Tabbed;
+
+    impl TextMeasurer for Tabbed {
+        fn width(&self, text: &str) -> u32 { Bold.width(text) }
+        fn styled_width(&self, text: &str, bold: bool, italic: bool) -> u32 {
+            Bold.styled_width(text, bold, italic)
+        }
+        fn advance(&self, _: char) -> u32 { 2 }
+        fn line_height(&self) -> u32 { 10 }
+        fn ascent(&self) -> i32 { 8 }
+        fn descent(&self) -> i32 { -2 }
+        fn tab_stop(&self) -> Option<u32> { Some(8) }
+    }
+
+    #[test]
+    fn test_line_measurer() {
+        let runs = [FontRun { start: 1, end: 3, bold: true, italic: false }];
+        let mut m = LineMeasurer { 
+            line: "abcd", runs: &runs, measurer: &Bold, inserted: None,
+            max_advance: None,
+        };
+        assert_eq!(m.width_at("abcd", 0), 10);
+        assert_eq!(m.width_at("cd", 2), 5);
+        assert_eq!(m.width("cd"), 4);
+        // Inserted text is measured with the text after it
+        m.inserted = Some((2, 7));
+        assert_eq!(m.width_at("ab", 0), 5);
+        assert_eq!(m.width_at("cd", 2), 12);
+    }
+
+    #[test]
+    fn test_tabbed_width() {
+        let m = TabbedMeasurer::new(FixedWidthMeasurer::new(1, 10), 4);
+        assert_eq!(m.width("a\tb"), 5);
+        assert_eq!(m.width("abcd\t"), 8);
+        assert_eq!(m.width("\t\t"), 8);
+        let runs = [FontRun { start: 0, end: 1, bold: true, italic: false }];
+        let mut m = LineMeasurer { 
+            line: "ab\tc", runs: &runs, measurer: &Tabbed, inserted: None,
+            max_advance: None,
+        };
+        assert_eq!(m.width_at("ab\tc", 0), 10);
+        assert_eq!(m.width_at("\tc", 2), 10);
+        m.inserted = Some((2, 4));
+        assert_eq!(m.width_at("ab\tc", 0), 18);
+    }
+
+    #[test]
+    fn test_cache_measures_once() {
+        let m = CachedMeasurer::with_capacity(Counting { calls: Cell::new(0) }, 2);
+        assert_eq!(m.width("abc"), 6);
+        assert_eq!(m.width("abc"), 6);
+        assert_eq!(m.inner().calls.get(), 1);
+        m.width("d");
+        m.width("e");
+        m.width("abc");
+        assert_eq!(m.inner().calls.get(), 4);
+    }
+}
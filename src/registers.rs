@@ -0,0 +1,154 @@
+//! The kill ring and named registers that killed and copied text is kept in.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+/// The number of kills kept by default.
+pub const DEFAULT_KILL_RING_SIZE: usize = 60;
+
+/// Recently killed text, newest first. Yanking inserts the newest entry and
+/// yanking again cycles through older ones.
+#[derive(Debug, Clone)]
+pub struct KillRing {
+    entries: VecDeque<String>,
+    capacity: usize,
+    /// The entry that the last yank inserted.
+    yank: usize,
+}
+
+impl KillRing {
+    /// Creates an empty kill ring that keeps at most `capacity` entries.
+    pub fn new(capacity: usize) -> KillRing {
+        KillRing { entries: VecDeque::new(), capacity: capacity, yank: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds a new entry, dropping the oldest one if the ring is full.
+    pub fn push(&mut self, text: &str) {
+        self.entries.push_front(String::from(text));
+        self.entries.truncate(self.capacity);
+        self.yank = 0;
+    }
+
+    /// Adds text to the end of the newest entry, for a kill that continues
+    /// forward from the previous one.
+    pub fn append(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(entry) => entry.push_str(text),
+            None => self.entries.push_front(String::from(text)),
+        }
+        self.yank = 0;
+    }
+
+    /// Adds text to the start of the newest entry, for a kill that continues
+    /// backward from the previous one.
+    pub fn prepend(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(entry) => entry.insert_str(0, text),
+            None => self.entries.push_front(String::from(text)),
+        }
+        self.yank = 0;
+    }
+
+    /// Returns the newest entry and starts cycling from it.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank = 0;
+        self.entries.front().map(|entry| entry.as_str())
+    }
+
+    /// Returns the entry before the one yanked last, wrapping around to the
+    /// newest after the oldest.
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank = (self.yank + 1) % self.entries.len();
+        Some(&self.entries[self.yank])
+    }
+}
+
+impl Default for KillRing {
+    fn default() -> KillRing {
+        KillRing::new(DEFAULT_KILL_RING_SIZE)
+    }
+}
+
+/// Text stored under single-character names. Like in Vim, the names are the
+/// letters and digits, and an uppercase letter appends to the register of
+/// its lowercase letter.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    named: HashMap<char, String>,
+}
+
+impl Registers {
+    pub fn new() -> Registers {
+        Registers::default()
+    }
+
+    /// Returns whether a character names a register.
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric()
+    }
+
+    /// Returns the text of a register, if it has any.
+    pub fn get(&self, name: char) -> Option<&str> {
+        self.named.get(&name.to_ascii_lowercase()).map(|text| text.as_str())
+    }
+
+    /// Stores text in a register, or appends it for an uppercase name.
+    pub fn set(&mut self, name: char, text: &str) {
+        if name.is_ascii_uppercase() {
+            self.named.entry(name.to_ascii_lowercase())
+                .or_insert_with(String::new).push_str(text);
+        } else {
+            self.named.insert(name, String::from(text));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill_ring_cycles() {
+        let mut ring = KillRing::new(2);
+        ring.push("a");
+        ring.push("b");
+        ring.push("c");
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.yank(), Some("c"));
+        assert_eq!(ring.yank_pop(), Some("b"));
+        assert_eq!(ring.yank_pop(), Some("c"));
+    }
+
+    #[test]
+    fn test_kill_ring_append() {
+        let mut ring = KillRing::default();
+        ring.append("b");
+        ring.append("c");
+        ring.prepend("a");
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring.yank(), Some("abc"));
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut registers = Registers::new();
+        registers.set('a', "x");
+        registers.set('A', "y");
+        assert_eq!(registers.get('a'), Some("xy"));
+        registers.set('a', "z");
+        assert_eq!(registers.get('A'), Some("z"));
+        assert_eq!(registers.get('b'), None);
+        assert!(! Registers::is_valid('"'));
+    }
+}
@@ -2,28 +2,175 @@ extern crate sdl2;
 extern crate sdl2_ttf;
 extern crate glorious;
 
+use std::env;
 use std::rc::Rc;
+use std::borrow::Cow;
+use std::cmp;
 use std::path::Path;
-use std::collections::HashMap;
-use sdl2::event::Event;
-use sdl2::keyboard::{LSHIFTMOD, RSHIFTMOD, LGUIMOD, RGUIMOD};
-use sdl2::keyboard::Keycode;
-use sdl2::render::{Renderer, Texture, TextureQuery};
+use std::time::{Duration, Instant};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::mouse::MouseButton;
+use sdl2::render::{Renderer, Texture};
 use sdl2::rect::{Rect, Point};
-use sdl2::pixels::Color;
-use sdl2_ttf::Font;
-use sdl2::surface::Surface;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2_ttf::{Font, Sdl2TtfContext};
 
-use textfield::Textfield;
-use common::WidthOfExt;
-use layout::{cursor_x_pos, cursor_pos, wrap_line};
+use textfield::{Textfield, word_occurrences};
+use editor::{Editor, Composition, FontRunsFn};
+use clipboard::{Clipboard, SdlClipboard, PrimarySelection};
+use commands::CommandRegistry;
+use keymap::{Keymap, KeyChord, KeyResult};
+use config::{Config, ConfigWatcher};
+use fonts::{load_font, load_fallbacks};
+use attributes::AttributedLines;
+use highlight::{Highlighter, SpanStyle, Theme};
+use common::StringSliceExt;
+use measure::{TextMeasurer, FontMeasurer, CachedMeasurer, FontRun, 
+    LineMeasurer, tabbed_width};
+use layout_cache::LayoutCache;
+use atlas::{GlyphAtlas, GlyphKey};
+use damage::Damage;
+use caret::{CaretShape, CaretState, Blink};
+use layout::{WrapMode, cursor_x_pos, cursor_pos, cursor_pos_before_wrap};
+use layout::{wrapped_glyph_positions, split_wrapped, row_indent, WrapStyle};
+use layout::{shown_whitespace, ShowWhitespace};
+use layout::average_char_width;
 use layout::{selection_single_line, selection_first_line, selection_middle_line, selection_last_line};
 
+/// Fonts used for the bold and italic variants of a text style. Missing
+/// variants fall back to the regular font.
+#[derive(Clone, Default)]
+pub struct FontVariants {
+    pub bold: Option<Rc<Font>>,
+    pub italic: Option<Rc<Font>>,
+    pub bold_italic: Option<Rc<Font>>,
+}
+
 #[derive(Clone)]
 pub struct TextStyle {
     pub font: Rc<Font>,
+    /// Measures text in the regular font.
+    pub measurer: CachedMeasurer<FontMeasurer>,
+    pub variants: FontVariants,
+    /// Fonts to use, in order, for characters the main font has no glyph for.
+    pub fallbacks: Vec<Rc<Font>>,
     pub color: Color,
     pub background: Option<Color>,
+    /// The distance between tab stops, which tabs advance to.
+    pub tab_stop: u32,
+}
+
+impl TextStyle {
+    /// Returns the font to render text of the given weight and slant with.
+    pub fn font_for(&self, bold: bool, italic: bool) -> &Rc<Font> {
+        let variant = match (bold, italic) {
+            (false, false) => None,
+            (true, false) => self.variants.bold.as_ref(),
+            (false, true) => self.variants.italic.as_ref(),
+            (true, true) => self.variants.bold_italic.as_ref(),
+        };
+        variant.unwrap_or(&self.font)
+    }
+    
+    /// Returns the font at the given position in the font stack, where 0 is
+    /// the font for the weight and slant and the rest are the fallbacks.
+    pub fn stack_font(&self, index: usize, bold: bool, italic: bool) 
+            -> &Rc<Font> {
+        if index == 0 {
+            self.font_for(bold, italic)
+        } else {
+            &self.fallbacks[index - 1]
+        }
+    }
+    
+    /// Returns the position in the font stack of the first font that has a
+    /// glyph for the given character.
+    pub fn font_index(&self, ch: char, bold: bool, italic: bool) -> usize {
+        if self.font_for(bold, italic).find_glyph(ch).is_some() {
+            return 0;
+        }
+        self.fallbacks.iter().position(|font| font.find_glyph(ch).is_some())
+            .map_or(0, |i| i + 1)
+    }
+    
+    /// Splits text into byte ranges that are drawn with the same font of the
+    /// font stack.
+    pub fn glyph_runs(&self, text: &str, bold: bool, italic: bool) 
+            -> Vec<(usize, usize, usize)> {
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+        for (i, ch) in text.char_indices() {
+            let index = self.font_index(ch, bold, italic);
+            let end = i + ch.len_utf8();
+            match runs.last_mut() {
+                Some(run) if run.2 == index => {
+                    run.1 = end;
+                    continue;
+                },
+                _ => {},
+            }
+            runs.push((i, end, index));
+        }
+        runs
+    }
+    
+    /// Measures text with a font of the font stack.
+    fn stack_width(&self, text: &str, index: usize, bold: bool, italic: bool) 
+            -> u32 {
+        if index == 0 && ! bold && ! italic {
+            self.measurer.width(text)
+        } else {
+            let font = self.stack_font(index, bold, italic).clone();
+            FontMeasurer::new(font).width(text)
+        }
+    }
+    
+    /// Measures text, using the fallback fonts for missing glyphs.
+    pub fn width_of(&self, text: &str, bold: bool, italic: bool) -> u32 {
+        if text.is_empty() {
+            return 0;
+        } else if self.fallbacks.is_empty() {
+            return self.stack_width(text, 0, bold, italic);
+        }
+        self.glyph_runs(text, bold, italic).into_iter()
+            .map(|(start, end, index)| {
+                self.stack_width(&text[start..end], index, bold, italic)
+            })
+            .sum()
+    }
+}
+
+/// Measures text in the regular font and its variants, with the fallback
+/// fonts for missing glyphs, and with tabs advancing to tab stops.
+impl TextMeasurer for TextStyle {
+    fn width(&self, text: &str) -> u32 {
+        self.styled_width(text, false, false)
+    }
+    
+    fn styled_width(&self, text: &str, bold: bool, italic: bool) -> u32 {
+        tabbed_width(text, self.tab_stop, |piece, _| {
+            self.width_of(piece, bold, italic)
+        })
+    }
+    
+    fn advance(&self, ch: char) -> u32 {
+        self.measurer.advance(ch)
+    }
+    
+    fn line_height(&self) -> u32 {
+        self.measurer.line_height()
+    }
+    
+    fn ascent(&self) -> i32 {
+        self.measurer.ascent()
+    }
+    
+    fn descent(&self) -> i32 {
+        self.measurer.descent()
+    }
+    
+    fn tab_stop(&self) -> Option<u32> {
+        Some(self.tab_stop)
+    }
 }
 
 #[derive(Clone)]
@@ -32,20 +179,143 @@ pub struct TextfieldStyle {
     pub x_pad: u32,
     pub y_pad: u32,
     pub cursor_color: Color,
+    pub caret_shape: CaretShape,
     pub selection_color: Color,
+    pub wrap_marker_color: Color,
+    pub show_whitespace: ShowWhitespace,
+    pub whitespace_color: Color,
+    /// The band behind the line with the cursor, if it is highlighted.
+    pub current_line_color: Option<Color>,
+    /// The columns that rulers are drawn at, in space widths.
+    pub rulers: Vec<u32>,
+    pub ruler_color: Color,
+    /// The outline of the other occurrences of the word at the cursor.
+    pub word_highlight_color: Option<Color>,
     pub background: Option<Color>,
+    pub theme: Theme,
 }
 
-
-
-/// Creates the surfaces (cpu images) of the given text rendered using the 
-/// given font and optionally wrapped to a given width limit in pixels.
-pub fn line_surface<'a>(line: &str, style: &TextStyle) -> Surface<'a> {
-    if let Some(background) = style.background {
-        style.font.render(line).shaded(style.color, background).unwrap()
+/// Returns the styled byte ranges covering the given line.
+fn line_styles(line: &str, lineno: usize, color: Color, theme: &Theme,
+        highlighter: Option<&Highlighter>, 
+        attributes: Option<&AttributedLines>) 
+        -> Vec<(usize, usize, SpanStyle)> {
+    let plain = SpanStyle::new(color);
+    let spans = highlighter.map_or(&[][..], |hl| hl.spans(lineno));
+    let styles = if spans.is_empty() {
+        vec![(0, line.len(), plain)]
     } else {
-        style.font.render(line).blended(style.color).unwrap()
+        let hl = highlighter.unwrap();
+        spans.iter().map(|span| {
+            let span_style = hl.scope(span)
+                .and_then(|scope| theme.style(scope))
+                .unwrap_or(plain);
+            (span.start, span.end, span_style)
+        }).collect()
+    };
+    
+    let runs = match attributes {
+        Some(attrs) if attrs.runs(lineno).len() > 0 => attrs.runs(lineno),
+        _ => return styles,
+    };
+    
+    // Layer the attribute runs on top of the highlighted spans
+    let bounds: Vec<_> = line.char_indices().map(|(i, _)| i)
+        .chain(Some(line.len())).collect();
+    let mut merged = Vec::new();
+    let mut col = 0;
+    for run in runs {
+        let end_col = cmp::min(col + run.len, bounds.len() - 1);
+        let (run_start, run_end) = (bounds[col], bounds[end_col]);
+        col = end_col;
+        for &(start, end, base) in &styles {
+            let start = cmp::max(start, run_start);
+            let end = cmp::min(end, run_end);
+            if start >= end {
+                continue;
+            }
+            let mut span_style = base;
+            let ref attrs = run.attrs;
+            span_style.bold |= attrs.bold;
+            span_style.italic |= attrs.italic;
+            span_style.underline |= attrs.underline;
+            span_style.strikethrough |= attrs.strikethrough;
+            if let Some(color) = attrs.foreground {
+                span_style.color = color;
+            }
+            if attrs.background.is_some() {
+                span_style.background = attrs.background;
+            }
+            merged.push((start, end, span_style));
+        }
+    }
+    merged
+}
+
+/// Inserts the text of an input method composition into a line at the given
+/// byte index, shifting the styles after it.
+fn splice_composition(line: &str, styles: Vec<(usize, usize, SpanStyle)>, 
+        at: usize, text: &str, text_style: SpanStyle) 
+        -> (String, Vec<(usize, usize, SpanStyle)>) {
+    let mut spliced = String::with_capacity(line.len() + text.len());
+    spliced.push_str(&line[..at]);
+    spliced.push_str(text);
+    spliced.push_str(&line[at..]);
+    
+    let len = text.len();
+    let composed = (at, at + len, text_style);
+    let mut shifted = Vec::with_capacity(styles.len() + 2);
+    let mut inserted = false;
+    for (start, end, span_style) in styles {
+        if end <= at {
+            shifted.push((start, end, span_style));
+            continue;
+        }
+        if ! inserted {
+            if start < at {
+                shifted.push((start, at, span_style));
+            }
+            shifted.push(composed);
+            inserted = true;
+        }
+        let start = cmp::max(start, at);
+        if start < end {
+            shifted.push((start + len, end + len, span_style));
+        }
+    }
+    if ! inserted {
+        shifted.push(composed);
     }
+    (spliced, shifted)
+}
+
+/// What a line is laid out with besides its text and the wrap width.
+#[derive(Clone, PartialEq)]
+pub struct LineKey {
+    pub runs: Vec<FontRun>,
+    /// The input method composition, on the line with the cursor.
+    pub composition: Option<Composition>,
+}
+
+/// Returns the ranges of the styles that aren't drawn with the regular font.
+fn font_runs(styles: &[(usize, usize, SpanStyle)]) -> Vec<FontRun> {
+    styles.iter()
+        .filter(|&&(_, _, ref s)| s.bold || s.italic)
+        .map(|&(start, end, ref s)| FontRun { 
+            start: start, end: end, bold: s.bold, italic: s.italic 
+        })
+        .collect()
+}
+
+/// Returns how the editor finds the font runs of lines, the way the view
+/// draws them.
+fn editor_font_runs(style: &TextfieldStyle) -> Box<FontRunsFn> {
+    let (color, theme) = (style.text.color, style.theme.clone());
+    Box::new(move |field, highlighter, lineno| {
+        let line = &field.lines[lineno];
+        font_runs(&line_styles(line, lineno, color, &theme, highlighter, 
+            field.attributes.as_ref()))
+    })
 }
 
 const ASCII_CHARS: [char; 95] = [
@@ -58,10 +328,10 @@ const ASCII_CHARS: [char; 95] = [
     'z', '{', '|', '}', '~',
 ];
 fn max_ascii_char_width(font: Rc<Font>) -> u32 {
+    let measurer = FontMeasurer::new(font);
     let mut max = 0;
     for &ch in ASCII_CHARS.iter() {
-        let (width, _) = font.size_of_char(ch)
-            .expect("Could not size ascii char");
+        let width = measurer.advance(ch);
         if width > max {
             max = width;
         }
@@ -69,19 +339,29 @@ fn max_ascii_char_width(font: Rc<Font>) -> u32 {
     max
 }
 
-/// Renders the given text field inside the given rect wrapping text at the
-/// given wrap_width.
+/// Renders the given text field inside the given rect wrapping text in the
+/// given mode. Only the damaged lines are drawn, over what was drawn
+/// before, and lines below the rect aren't laid out. Lines are only wrapped
+/// again when the layout doesn't have them. An input method composition is
+/// drawn underlined at the cursor, and the caret in the given state. The
+/// occurrences of `word` away from the cursor are outlined. Returns the rect
+/// of the cursor if its line was drawn.
 pub fn render_textfield<'a>(field: &Textfield, rect: Rect,
         style: &TextfieldStyle, renderer: &mut Renderer, 
-        wrap_width: Option<u32>, max_char_width: Option<u32>,
-        text_cache: &mut HashMap<String, Texture>, 
-        wrap_cache: &mut HashMap<String, Vec<usize>>) {
+        wrap: WrapMode, wrap_style: WrapStyle, max_char_width: Option<u32>,
+        atlas: &mut GlyphAtlas, 
+        layout: &mut LayoutCache<LineKey>,
+        highlighter: Option<&Highlighter>, 
+        composition: Option<&Composition>, word: Option<&str>, 
+        caret: CaretState, damage: &Damage) -> Option<Rect> {
     
     renderer.set_clip_rect(Some(rect));
     
-    if let Some(color) = style.background {
-        renderer.set_draw_color(color);
-        renderer.clear();
+    if damage.is_all() {
+        if let Some(color) = style.background {
+            renderer.set_draw_color(color);
+            renderer.clear();
+        }
     }
     
     let (first, last) = field.cursor.order(&field.selection_marker);
@@ -89,68 +369,129 @@ pub fn render_textfield<'a>(field: &Textfield, rect: Rect,
     
     let x = rect.x() + style.x_pad as i32;
     let y = rect.y() + style.y_pad as i32;
-    let height = style.text.font.recommended_line_height();
-    let mut visual_lineno = 0;
-    // Use a heuristic to skip the glyph size check
-    let should_wrap = |t: &str| {
-        if let Some(width) = max_char_width {
-            if t.len() as u32 * width <= wrap_width.unwrap() {
-                false
-            } else {
-                style.text.font.width_of(t) > wrap_width.unwrap()
+    let height = style.text.measurer.line_height();
+    let char_width = average_char_width(&style.text.measurer);
+    let wrap_width = wrap.width(text_width(style, rect), char_width);
+    let width = wrap_width.unwrap_or(text_width(style, rect));
+    let attributes = field.attributes.as_ref();
+    let cursor = field.cons_cursor();
+    let mut cursor_rect = None;
+    let mut caret_at = None;
+    layout.set_wrap(wrap, wrap_width);
+    if layout.len() != field.lines.len() {
+        layout.reset(field.lines.len());
+    }
+    // Whether a line got more or fewer visual lines, moving the ones after it
+    let mut shifted = false;
+    let first_line = layout.scroll_anchor().line;
+    let top = (layout.scroll_top() as u32 * height) as i32;
+    
+    for (lineno, line) in field.lines.iter().enumerate().skip(first_line) {
+        let y_pos = y + layout.y_of_line(lineno, height) - top;
+        if y_pos >= rect.bottom() {
+            break;
+        }
+        if ! shifted && ! damage.contains(lineno) {
+            continue;
+        }
+        let styles = line_styles(line, lineno, style.text.color, &style.theme,
+            highlighter, attributes);
+        let mut cursor_col = cursor.col;
+        let line_composition = if lineno == cursor.line { 
+            composition 
+        } else { 
+            None 
+        };
+        let (line, styles): (Cow<str>, _) = match line_composition {
+            Some(composition) => {
+                let at = line.len() - line.slice_after(cursor.col).len();
+                let text_style = SpanStyle { 
+                    underline: true, ..SpanStyle::new(style.text.color) 
+                };
+                let (spliced, styles) = splice_composition(line, styles, at, 
+                    &composition.text, text_style);
+                cursor_col += composition.cursor;
+                (Cow::Owned(spliced), styles)
+            },
+            None => (Cow::Borrowed(line), styles),
+        };
+        let line: &str = &line;
+        let runs = font_runs(&styles);
+        let measurer = LineMeasurer {
+            line: line, runs: &runs, measurer: &style.text, inserted: None,
+            max_advance: max_char_width,
+        };
+        let key = LineKey { 
+            runs: runs.clone(), composition: line_composition.cloned(),
+        };
+        let indent = wrap_width.map_or(0, |wrap_width| {
+            wrap_style.continuation_indent(line, &measurer, wrap_width, 
+                char_width)
+        });
+        let cached = layout.breaks(lineno, &key).map(|breaks| breaks.to_vec());
+        let breaks = match cached {
+            Some(breaks) => breaks,
+            None => {
+                let breaks = match wrap_width {
+                    Some(wrap_width) => {
+                        wrap.wrap_indented(line, &measurer, wrap_width, indent)
+                    },
+                    None => Vec::new(),
+                };
+                shifted |= layout.set_breaks(lineno, key, breaks.clone());
+                breaks
+            },
+        };
+        let lines = split_wrapped(line, &breaks);
+        
+        if ! damage.is_all() {
+            if let Some(color) = style.background {
+                renderer.set_draw_color(color);
+                let band = Rect::new(rect.x(), y_pos, rect.width(), 
+                    lines.len() as u32 * height);
+                renderer.fill_rect(band).expect("Could not clear a line");
             }
-        } else {
-            style.text.font.width_of(t) > wrap_width.unwrap()
         }
-    };
-    let width_check = |t: &str| {
-        style.text.font.width_of(t)
-    };
-    let width = wrap_width.unwrap_or(rect.width() - style.x_pad * 2);
-    
-    for (lineno, line) in field.lines.iter().enumerate() {
-        let y_pos = y + (visual_lineno as u32 * height) as i32;
-        let lines = if let Some(wrap_width) = wrap_width {
-            let indices = wrap_cache.entry(line.clone())
-                .or_insert_with(|| wrap_line(line, &should_wrap));
-            if ! indices.is_empty() {
-                let mut lines = Vec::new();
-                let mut start = 0;
-                for &mut index in indices {
-                    lines.push(&line[start..index]);
-                    start = index;
-                }
-                lines.push(&line[start..]);
-                lines
-            } else {
-                vec![line.as_str()]
+        
+        // The current line band and the rulers go beneath everything else
+        let line_bottom = y_pos + (lines.len() as u32 * height) as i32;
+        if let Some(color) = style.current_line_color {
+            if lineno == cursor.line {
+                renderer.set_draw_color(color);
+                let band = Rect::new(rect.x(), y_pos, rect.width(), 
+                    lines.len() as u32 * height);
+                renderer.fill_rect(band)
+                    .expect("Could not highlight the current line");
             }
-        } else {
-            vec![line.as_str()]
-        };
+        }
+        draw_rulers(renderer, style, x, y_pos, line_bottom);
+        
         // Selection
         if has_selection {
             // Same line
             if lineno == first.line && lineno == last.line {
                 renderer.set_draw_color(style.selection_color);
-                for mut sel in selection_single_line(&lines, first.col, last.col,
-                        &width_check, width, height) {
+                for sel in selection_single_line(&lines, first.col, last.col,
+                        &measurer, width) {
+                    let mut sel = indent_rect(sel, height, indent);
                     sel.offset(x, y_pos);
                     renderer.fill_rect(sel).expect("Selection fill rect");
                 }
             // First line
             } else if lineno == first.line {
                 renderer.set_draw_color(style.selection_color);
-                for mut sel in selection_first_line(&lines, first.col, 
-                        &width_check, width, height) {
+                for sel in selection_first_line(&lines, first.col, 
+                        &measurer, width) {
+                    let mut sel = indent_rect(sel, height, indent);
                     sel.offset(x, y_pos);
                     renderer.fill_rect(sel).expect("Selection fill rect");
                 }
             // Last line
             } else if lineno == last.line {
                 renderer.set_draw_color(style.selection_color);
-                for mut sel in selection_last_line(&lines, last.col, 
-                        &width_check, width, height) {
+                for sel in selection_last_line(&lines, last.col, 
+                        &measurer, width) {
+                    let mut sel = indent_rect(sel, height, indent);
                     sel.offset(x, y_pos);
                     renderer.fill_rect(sel).expect("Selection fill rect");
                 }
@@ -161,188 +502,626 @@ pub fn render_textfield<'a>(field: &Textfield, rect: Rect,
                 sel.offset(x, y_pos);
                 renderer.fill_rect(sel).expect("Selection fill rect");
             }
+        }
+        
+        // Other occurrences of the word at the cursor
+        if let (Some(word), Some(color)) = (word, style.word_highlight_color) {
+            renderer.set_draw_color(color);
+            for (start, end) in word_occurrences(line, word) {
+                let at_cursor = lineno == cursor.line 
+                    && start <= cursor_col && cursor_col <= end;
+                if at_cursor || line_composition.is_some() {
+                    continue;
+                }
+                for sel in selection_single_line(&lines, start, end, 
+                        &measurer, width) {
+                    let mut sel = indent_rect(sel, height, indent);
+                    sel.offset(x, y_pos);
+                    renderer.draw_rect(sel).expect("Could not outline a word");
+                }
+            }
+        }
         
         // Cursor
-        } else if lineno == first.line {
-            let (cx, cy) = if let Some(wrap_width) = wrap_width {
-                let (cl, cx) = cursor_pos(first.col, &lines, &width_check);
-                (x + cx, y_pos + (cl as u32 * height) as i32)
+        if lineno == cursor.line {
+            let (cl, cx, cy) = if wrap_width.is_some() {
+                // The end of a visual line and the start of the next are the
+                // same column, so the field tells which one the cursor is at
+                let before_wrap = line_composition.is_none() 
+                    && field.wrap_end == Some(cursor);
+                let (cl, cx) = if before_wrap {
+                    cursor_pos_before_wrap(cursor_col, &lines, &measurer)
+                } else {
+                    cursor_pos(cursor_col, &lines, &measurer)
+                };
+                let cx = cx + row_indent(cl, indent);
+                (cl, x + cx, y_pos + (cl as u32 * height) as i32)
             } else {
-                let cx = cursor_x_pos(first.col, line, &width_check);
-                (x + cx, y_pos)
+                let cx = cursor_x_pos(cursor_col, line, &measurer);
+                (0, x + cx, y_pos)
             };
+            cursor_rect = Some(Rect::new(cx, cy, 1, height));
             
-            let start = Point::new(cx, cy);
-            let end = Point::new(cx, cy + height as i32);
-            renderer.set_draw_color(style.cursor_color);
-            renderer.draw_line(start, end).expect("Could not draw cursor");
+            if ! has_selection && caret != CaretState::Hidden {
+                let before_wrap = wrap_width.is_some() 
+                    && line_composition.is_none() 
+                    && field.wrap_end == Some(cursor);
+                // The column the cursor's visual line starts at
+                let row_col: usize = lines[..cl].iter()
+                    .map(|row| row.chars().count()).sum();
+                caret_at = Some((cx, cy, cl, row_col, before_wrap));
+            }
         }
         
         
         // Text
-        let line_count = lines.len();
-        for (i, line) in lines.into_iter().enumerate() {
-            if line.is_empty() {
-                continue;
+        let positions = wrapped_glyph_positions(&lines, &measurer);
+        let bounds: Vec<usize> = line.char_indices().map(|(i, _)| i)
+            .chain(Some(line.len())).collect();
+        let col_of = |index: usize| bounds.binary_search(&index)
+            .expect("Styles have to start and end between characters");
+        let in_selection = has_selection 
+            && first.line <= lineno && lineno <= last.line;
+        let selected = if in_selection {
+            let start = if lineno == first.line { first.col } else { 0 };
+            let end = if lineno == last.line { last.col } else { bounds.len() };
+            Some((start, end))
+        } else {
+            None
+        };
+        let (shown, show_end) = shown_whitespace(line, style.show_whitespace,
+            selected, in_selection && lineno < last.line);
+        let chars: Vec<char> = line.chars().collect();
+        let row_count = lines.len();
+        let mut seg_start = 0;
+        for (i, segment) in lines.into_iter().enumerate() {
+            let seg_end = seg_start + segment.len();
+            let seg_y = y_pos + (i as u32 * height) as i32;
+            let seg_x = x + row_indent(i, indent) 
+                - positions[col_of(seg_start)];
+            if i > 0 && wrap_style.markers {
+                draw_wrap_marker(renderer, style, x, seg_y, height);
+            }
+            for &(start, end, ref span_style) in &styles {
+                let start = if start > seg_start { start } else { seg_start };
+                let end = if end < seg_end { end } else { seg_end };
+                if start >= end {
+                    continue;
+                }
+                let start_x = seg_x + positions[col_of(start)];
+                let end_x = seg_x + positions[col_of(end)];
+                let background = span_style.background
+                    .or(style.text.background);
+                if let Some(background) = background {
+                    renderer.set_draw_color(background);
+                    renderer.fill_rect(Rect::new(start_x, seg_y, 
+                            (end_x - start_x) as u32, height))
+                        .expect("Could not fill text background");
+                }
+                
+                let (bold, italic) = (span_style.bold, span_style.italic);
+                let piece = &line[start..end];
+                let mut col = col_of(start);
+                for (run_start, run_end, font_index) in 
+                        style.text.glyph_runs(piece, bold, italic) {
+                    let font = style.text.stack_font(font_index, bold, italic);
+                    for ch in piece[run_start..run_end].chars() {
+                        let glyph_x = seg_x + positions[col];
+                        col += 1;
+                        if ch.is_whitespace() {
+                            continue;
+                        }
+                        let key = GlyphKey { 
+                            ch: ch, font_index: font_index, 
+                            bold: bold, italic: italic,
+                        };
+                        if let Some(glyph) = atlas.glyph(renderer, key, font) {
+                            atlas.draw(renderer, glyph, glyph_x, seg_y, 
+                                span_style.color);
+                        }
+                    }
+                }
+                
+                let ascent = style.text.font_for(bold, italic).ascent();
+                let mut decorations = Vec::new();
+                if span_style.underline {
+                    decorations.push(seg_y + ascent + 1);
+                }
+                if span_style.strikethrough {
+                    decorations.push(seg_y + ascent * 2 / 3);
+                }
+                for dy in decorations {
+                    renderer.set_draw_color(span_style.color);
+                    renderer.draw_line(Point::new(start_x, dy), 
+                        Point::new(end_x, dy))
+                        .expect("Could not draw text decoration");
+                }
             }
-            let mut texture = text_cache.entry(String::from(line)).or_insert_with(|| {
-                let surface = line_surface(line, &style.text);
-                renderer.create_texture_from_surface(surface)
-                    .expect("Could not create text texture")
-            });
-            let TextureQuery { width: w, height: h, ..} = texture.query();
-            let target = Rect::new(
-                x, y_pos + (i as u32 * height) as i32, 
-                w, h
-            );
             
-            renderer.copy(&mut texture, None, Some(target));
+            // Whitespace marks go over the text backgrounds
+            let (start_col, end_col) = (col_of(seg_start), col_of(seg_end));
+            renderer.set_draw_color(style.whitespace_color);
+            for &col in shown.iter()
+                    .filter(|&&col| start_col <= col && col < end_col) {
+                let mark_x = seg_x + positions[col];
+                let mark_width = (positions[col + 1] - positions[col]) as u32;
+                draw_whitespace_mark(renderer, chars[col], mark_x, seg_y, 
+                    mark_width, height);
+            }
+            if show_end && i + 1 == row_count {
+                draw_line_end_mark(renderer, seg_x + positions[end_col], 
+                    seg_y, height);
+            }
+            seg_start = seg_end;
+        }
+        
+        // The caret goes over the text, so a block can invert its character
+        if let Some((cx, cy, cl, row_col, before_wrap)) = caret_at.take() {
+            let under = if before_wrap { None } else { chars.get(cursor_col) };
+            let char_width = match under {
+                Some(_) => positions[cursor_col + 1] - positions[cursor_col],
+                None => measurer.advance(' ') as i32,
+            };
+            let char_width = cmp::max(char_width, 1) as u32;
+            // Overwrite mode shows a block, or an underline when the block is
+            // the usual caret
+            let caret_shape = match (field.overwrite, style.caret_shape) {
+                (false, shape) => shape,
+                (true, CaretShape::Block) => CaretShape::Underline,
+                (true, _) => CaretShape::Block,
+            };
+            let shape = caret_shape.rect(cx, cy, char_width, height);
+            renderer.set_draw_color(style.cursor_color);
+            if caret == CaretState::Hollow {
+                let outline = CaretShape::Block.rect(cx, cy, char_width, height);
+                renderer.draw_rect(outline).expect("Could not draw the caret");
+            } else {
+                renderer.fill_rect(shape).expect("Could not draw the caret");
+            }
+            // Only the block of overwrite mode inverts the character, which
+            // is drawn where the text loop draws it, without the kerning the
+            // caret is moved by
+            match under {
+                Some(&ch) if field.overwrite 
+                        && caret_shape == CaretShape::Block 
+                        && caret == CaretState::Solid 
+                        && ! ch.is_whitespace() => {
+                    let at = bounds[cursor_col];
+                    let span_style = styles.iter()
+                        .find(|&&(start, end, _)| start <= at && at < end)
+                        .map_or(SpanStyle::new(style.text.color), |s| s.2);
+                    let inverse = span_style.background
+                        .or(style.text.background).or(style.background)
+                        .unwrap_or(Color::RGB(255, 255, 255));
+                    let glyph_x = x + row_indent(cl, indent) 
+                        + positions[cursor_col] - positions[row_col];
+                    draw_glyph(renderer, atlas, &style.text, 
+                        &line[at..at + ch.len_utf8()], span_style, glyph_x, 
+                        cy, inverse);
+                },
+                _ => {},
+            }
+        }
+    }
+    
+    // Clear the space that removed lines leave behind
+    let end_y = y + layout.y_of_line(field.lines.len(), height) - top;
+    if (damage.contains_end() || shifted) && end_y < rect.bottom() {
+        if let (Some(color), false) = (style.background, damage.is_all()) {
+            renderer.set_draw_color(color);
+            let below = Rect::new(rect.x(), end_y, rect.width(), 
+                (rect.bottom() - end_y) as u32);
+            renderer.fill_rect(below).expect("Could not clear below the text");
         }
-        visual_lineno += line_count;
+        draw_rulers(renderer, style, x, end_y, rect.bottom());
     }
     
     renderer.set_clip_rect(None);
+    cursor_rect
 }
 
-const SCREEN_WIDTH: u32 = 800;
-const SCREEN_HEIGHT: u32 = 600;
-pub fn main(field: &mut Textfield) {
+/// Draws the column rulers from `top` down to `bottom`, with the first
+/// column at `x`.
+fn draw_rulers(renderer: &mut Renderer, style: &TextfieldStyle, x: i32, 
+        top: i32, bottom: i32) {
+    if style.rulers.is_empty() || top >= bottom {
+        return;
+    }
+    let space = style.text.measurer.advance(' ') as i32;
+    renderer.set_draw_color(style.ruler_color);
+    for &col in &style.rulers {
+        let ruler_x = x + col as i32 * space;
+        renderer.draw_line(Point::new(ruler_x, top), 
+                Point::new(ruler_x, bottom - 1))
+            .expect("Could not draw a ruler");
+    }
+}
+
+/// Draws a single character in the given color, like the text loop does.
+fn draw_glyph(renderer: &mut Renderer, atlas: &mut GlyphAtlas, 
+        style: &TextStyle, text: &str, span_style: SpanStyle, x: i32, y: i32,
+        color: Color) {
+    let (bold, italic) = (span_style.bold, span_style.italic);
+    for (start, end, font_index) in style.glyph_runs(text, bold, italic) {
+        let font = style.stack_font(font_index, bold, italic);
+        for ch in text[start..end].chars() {
+            let key = GlyphKey { 
+                ch: ch, font_index: font_index, bold: bold, italic: italic,
+            };
+            if let Some(glyph) = atlas.glyph(renderer, key, font) {
+                atlas.draw(renderer, glyph, x, y, color);
+            }
+        }
+    }
+}
+
+/// Moves a selection rect on a continuation line right by the indent of the
+/// continuation lines. Rects that cover several visual lines span the width
+/// and stay where they are.
+fn indent_rect(rect: Rect, line_height: u32, indent: u32) -> Rect {
+    if rect.y() > 0 && rect.height() == line_height {
+        Rect::new(rect.x() + indent as i32, rect.y(), rect.width(), 
+            rect.height())
+    } else {
+        rect
+    }
+}
+
+/// Draws a hooked arrow in the left padding in front of a continuation line
+/// that starts at `x`, if the padding has room for it.
+fn draw_wrap_marker(renderer: &mut Renderer, style: &TextfieldStyle, x: i32, 
+        y: i32, line_height: u32) {
+    let size = cmp::min(style.x_pad as i32 - 2, line_height as i32 / 2);
+    if size < 3 {
+        return;
+    }
+    let right = x - 2;
+    let left = right - size;
+    let mid_y = y + line_height as i32 / 2;
+    renderer.set_draw_color(style.wrap_marker_color);
+    let points = [
+        Point::new(left, mid_y - size / 2), Point::new(left, mid_y),
+        Point::new(right, mid_y), Point::new(right - size / 2, mid_y - size / 2),
+    ];
+    renderer.draw_lines(&points[..]).expect("Could not draw a wrap marker");
+    renderer.draw_line(Point::new(right, mid_y), 
+            Point::new(right - size / 2, mid_y + size / 2))
+        .expect("Could not draw a wrap marker");
+}
+
+/// Draws a faint mark for a whitespace character that takes up `width`
+/// pixels at `x`: an arrow for a tab and a dot for anything else.
+fn draw_whitespace_mark(renderer: &mut Renderer, ch: char, x: i32, y: i32, 
+        width: u32, line_height: u32) {
+    let mid_y = y + line_height as i32 / 2;
+    if ch == '\t' {
+        if width < 4 {
+            return;
+        }
+        let left = x + 1;
+        let right = x + width as i32 - 2;
+        let head = cmp::min(line_height as i32 / 6, (right - left) / 2);
+        let points = [
+            Point::new(right - head, mid_y - head), Point::new(right, mid_y),
+            Point::new(right - head, mid_y + head),
+        ];
+        renderer.draw_line(Point::new(left, mid_y), Point::new(right, mid_y))
+            .expect("Could not draw a tab mark");
+        renderer.draw_lines(&points[..]).expect("Could not draw a tab mark");
+    } else {
+        let dot_x = x + width as i32 / 2;
+        let dot = Rect::new(dot_x - 1, mid_y - 1, 2, 2);
+        renderer.fill_rect(dot).expect("Could not draw a space mark");
+    }
+}
+
+/// Draws a pilcrow-like bar at the end of a line.
+fn draw_line_end_mark(renderer: &mut Renderer, x: i32, y: i32, 
+        line_height: u32) {
+    let top = y + line_height as i32 / 4;
+    let bottom = y + line_height as i32 * 3 / 4;
+    let mark_x = x + 2;
+    renderer.draw_line(Point::new(mark_x, top), Point::new(mark_x, bottom))
+        .expect("Could not draw a line end mark");
+    renderer.draw_line(Point::new(mark_x, top), Point::new(mark_x + 3, top))
+        .expect("Could not draw a line end mark");
+}
+
+/// Loads the fonts and builds the text field style described by the config.
+pub fn build_style(ttf: &Sdl2TtfContext, config: &Config) -> TextfieldStyle {
+    let font_options = &config.font;
+    let font = load_font(ttf, font_options, sdl2_ttf::STYLE_NORMAL);
+    let load_variant = |font_style| {
+        Some(Rc::new(load_font(ttf, font_options, font_style)))
+    };
+    let variants = FontVariants {
+        bold: load_variant(sdl2_ttf::STYLE_BOLD),
+        italic: load_variant(sdl2_ttf::STYLE_ITALIC),
+        bold_italic: load_variant(sdl2_ttf::STYLE_BOLD | sdl2_ttf::STYLE_ITALIC),
+    };
+    println!("height/ascent/descent: {} | {} | {}", 
+        font.height(), font.ascent(), font.descent());
+    println!("Line skip: {}", font.recommended_line_height());
+    
+    let font = Rc::new(font);
+    let measurer = CachedMeasurer::new(FontMeasurer::new(font.clone()));
+    let tab_stop = cmp::max(config.tabs.width as u32 * measurer.advance(' '), 1);
+    let text_style = TextStyle {
+        measurer: measurer,
+        font: font, variants: variants, 
+        fallbacks: load_fallbacks(ttf, font_options.size),
+        color: config.style.text_color, 
+        background: config.style.text_background,
+        tab_stop: tab_stop,
+    };
+    
+    TextfieldStyle { 
+        text: text_style, 
+        x_pad: config.style.x_pad, y_pad: config.style.y_pad,
+        cursor_color: config.style.cursor_color, 
+        caret_shape: config.caret.shape,
+        selection_color: config.style.selection_color, 
+        wrap_marker_color: config.style.wrap_marker_color,
+        show_whitespace: config.style.show_whitespace,
+        whitespace_color: config.style.whitespace_color,
+        current_line_color: config.style.current_line_color,
+        rulers: config.style.rulers.clone(),
+        ruler_color: config.style.ruler_color,
+        word_highlight_color: config.style.word_highlight,
+        // Lines are cleared with the background when they are drawn again
+        background: config.style.background.or(Some(config.window.clear_color)),
+        theme: Theme::default(),
+    }
+}
+
+/// Returns the widest ascii glyph width of the regular and bold fonts, for 
+/// the wrapping heuristic.
+pub fn widest_char_width(style: &TextfieldStyle) -> u32 {
+    // Bold glyphs are wider, so the heuristic has to use the widest variant
+    cmp::max(
+        max_ascii_char_width(style.text.font.clone()),
+        max_ascii_char_width(style.text.font_for(true, false).clone()))
+}
+
+/// Returns the rect of the text field in a window of the given size.
+pub fn field_rect(config: &Config, width: u32, height: u32) -> Rect {
+    let margin = config.window.margin;
+    Rect::new(margin as i32, margin as i32, 
+        cmp::max(width.saturating_sub(margin * 2), 1), 
+        cmp::max(height.saturating_sub(margin * 2), 1))
+}
+
+/// Returns the width of the text inside a field rect.
+pub fn text_width(style: &TextfieldStyle, rect: Rect) -> u32 {
+    cmp::max(rect.width().saturating_sub(style.x_pad * 2), 1)
+}
+
+/// Returns how many visual lines fit in a field rect.
+pub fn view_rows(style: &TextfieldStyle, rect: Rect) -> usize {
+    let height = rect.height().saturating_sub(style.y_pad * 2);
+    cmp::max(height / style.text.measurer.line_height(), 1) as usize
+}
+
+/// How many visual lines a step of the mouse wheel scrolls.
+const WHEEL_ROWS: isize = 3;
+
+/// How often to check whether the config file changed.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the cursor has to stay on a word before its other occurrences
+/// are outlined.
+const WORD_HIGHLIGHT_DELAY: Duration = Duration::from_millis(300);
+
+/// How often to try again to set the primary selection while the last
+/// process setting it is still running.
+const PRIMARY_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Returns how long the caret is shown and hidden for, if it blinks.
+fn blink_interval(config: &Config) -> Option<Duration> {
+    match config.caret.blink {
+        0 => None,
+        ms => Some(Duration::from_millis(ms as u64)),
+    }
+}
+
+/// Runs the editor. The config is reloaded when the watched file changes,
+/// except for the window size which is only used when the window is created.
+/// The field follows the size of the window and keeps the cursor in view.
+pub fn main(editor: &mut Editor, mut config: Config,
+        mut watcher: Option<ConfigWatcher>) {
     let context = sdl2::init().unwrap();
     let video = context.video().unwrap();
-    let mut clipboard = String::new();
     let ttf = sdl2_ttf::init().unwrap();
+    editor.clipboard = Box::new(SdlClipboard::new(&video));
+    editor.primary = PrimarySelection::detect()
+        .map(|primary| Box::new(primary) as Box<dyn Clipboard>);
     
-    let window = video.window("Editor", SCREEN_WIDTH, SCREEN_HEIGHT)
-        .position_centered().build().unwrap();
+    let (mut screen_width, mut screen_height) = (config.window.width, 
+        config.window.height);
+    let window = video.window("Editor", screen_width, screen_height)
+        .position_centered().resizable().build().unwrap();
         
     let mut renderer = window.renderer().build().unwrap();
     
-    //let font = ttf.load_font(Path::new("Monoid-Regular.ttf"), 16).unwrap();
-    let font_path = "/Library/Fonts/Verdana.ttf";
-    let font = ttf.load_font(Path::new(font_path), 16).unwrap();
-    println!("height/ascent/descent: {} | {} | {}", 
-        font.height(), font.ascent(), font.descent());
-    println!("Line skip: {}", font.recommended_line_height());
-    
-    let pink = Color::RGBA(255, 180, 220, 255);
-    let red = Color::RGBA(255, 0, 0, 255);
-    let white = Color::RGBA(255, 255, 255, 255);
-    let black = Color::RGBA(0, 0, 0, 255);
-    
-    let clear_color = white;
-    renderer.set_draw_color(clear_color);
+    renderer.set_draw_color(config.window.clear_color);
     renderer.clear();
     
-    let text_style = TextStyle {
-        font: Rc::new(font), color: black, background: None,
-    };
-    
-    let style = TextfieldStyle { 
-        text: text_style, x_pad: 10, y_pad: 10,
-        cursor_color: red, selection_color: pink, 
-        background: Some(Color::RGBA(220, 220, 255, 255)),
-    };
+    let mut style = build_style(&ttf, &config);
+    editor.measurer = Box::new(style.text.clone());
+    editor.font_runs = editor_font_runs(&style);
     
     renderer.present();
-    let mut limiter = glorious::FrameLimiter::new(30);
-    let mut dirty = true;
-    let mut text_cache = HashMap::new();
-    let mut wrap_cache = HashMap::new();
-    let max_char_width = max_ascii_char_width(style.text.font.clone());
+    let mut limiter = glorious::FrameLimiter::new(config.window.frame_limit);
+    let mut event_pump = context.event_pump().unwrap();
+    // Lines are drawn into a texture that keeps what was drawn between frames
+    let supports_targets = renderer.render_target().is_some();
+    let mut target: Option<Texture> = None;
+    let mut damage = Damage::new();
+    damage.all();
+    let mut present = false;
+    let mut atlas = GlyphAtlas::new();
+    let mut layout = LayoutCache::new();
+    // Whether to scroll to the cursor after the next edit, move or resize
+    let mut follow_cursor = true;
+    let mut max_char_width = widest_char_width(&style);
+    let mut last_config_check = Instant::now();
+    let text_input = video.text_input();
+    text_input.start();
+    let mut last_cursor_rect = None;
+    let commands = CommandRegistry::builtin();
+    let mut keymap = Keymap::with_overrides(&config.keys)
+        .expect("The config has invalid key bindings");
+    let mut blink = Blink::new(blink_interval(&config), Instant::now());
+    let mut focused = true;
+    let mut last_caret = CaretState::Solid;
+    // The word at the cursor, since when it is there, and the word outlined
+    let mut word_at_cursor: Option<String> = None;
+    let mut word_since = Instant::now();
+    let mut outlined_word: Option<String> = None;
     
     'mainloop: loop {
-        for event in context.event_pump().unwrap().poll_iter() {
+        let primary_waiting = editor.flush_primary();
+        // Sleep until something happens when there is nothing to draw
+        let mut events = Vec::new();
+        if damage.is_empty() && ! present {
+            let mut wait = if watcher.is_some() {
+                let elapsed = last_config_check.elapsed();
+                Some(CONFIG_CHECK_INTERVAL.checked_sub(elapsed)
+                    .unwrap_or(Duration::from_secs(0)))
+            } else {
+                None
+            };
+            // Wake up to blink the caret and to outline the word
+            let mut wake = Vec::new();
+            if focused {
+                wake.extend(blink.until_toggle(Instant::now()));
+            }
+            if word_at_cursor.is_some() && word_at_cursor != outlined_word {
+                wake.push(WORD_HIGHLIGHT_DELAY.checked_sub(word_since.elapsed())
+                    .unwrap_or(Duration::from_secs(0)));
+            }
+            if primary_waiting {
+                wake.push(PRIMARY_RETRY_INTERVAL);
+            }
+            for until in wake {
+                wait = Some(wait.map_or(until, |w| cmp::min(w, until)));
+            }
+            let event = match wait {
+                Some(wait) => {
+                    let ms = wait.as_secs() as u32 * 1000 
+                        + wait.subsec_nanos() / 1_000_000;
+                    event_pump.wait_event_timeout(ms)
+                },
+                None => Some(event_pump.wait_event()),
+            };
+            events.extend(event);
+        }
+        events.extend(event_pump.poll_iter());
+        
+        let (old_cursor, old_marker) = (editor.field.cursor, 
+            editor.field.selection_marker);
+        let old_wrap_end = editor.field.wrap_end;
+        let old_overwrite = editor.field.overwrite;
+        // Moving by visual lines wraps the lines like the view does
+        editor.view_width = text_width(&style, 
+            field_rect(&config, screen_width, screen_height));
+        let old_composition = editor.composition.clone();
+        let old_wrap = editor.wrap;
+        let mut typed = false;
+        for event in events {
+            match event {
+                Event::KeyDown { .. } | Event::TextInput { .. } | 
+                        Event::TextEditing { .. } => {
+                    typed = true;
+                },
+                _ => {},
+            }
             match event {
-                Event::Quit{..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape), ..} => {
+                Event::Quit{..} => {
                     break 'mainloop;
                 },
-                Event::KeyDown { keycode, keymod, ..} => {
-                    if keymod.is_empty() {
-                        match keycode {
-                            Some(Keycode::Left) => {
-                                field.left();
-                                dirty = true;
-                            },
-                            Some(Keycode::Right) => {
-                                field.right();
-                                dirty = true;
-                            },
-                            Some(Keycode::Up) => {
-                                field.up();
-                                dirty = true;
-                            },
-                            Some(Keycode::Down) => {
-                                field.down();
-                                dirty = true;
-                            },
-                            Some(Keycode::Backspace) => {
-                                field.delete_previous();
-                                dirty = true;
-                            },
-                            Some(Keycode::Delete) => {
-                                field.delete_next();
-                                dirty = true;
-                            },
-                            Some(Keycode::Return) => {
-                                field.insert("\n");
-                                dirty = true;
+                // The input method handles the keys while composing
+                Event::KeyDown { .. } if editor.composition.is_some() => {},
+                Event::KeyDown { keycode: Some(keycode), keymod, ..} => {
+                    let chord = KeyChord::from_sdl(keycode, keymod);
+                    match keymap.press(chord) {
+                        KeyResult::Command(name) => {
+                            if ! commands.run(&name, editor) {
+                                println!("Unknown command: {}", name);
                             }
-                            other => {
-                                println!("Key down: {:?}", other);
-                            },
-                        }
-                    } else if keymod == RSHIFTMOD || keymod == LSHIFTMOD {
-                        match keycode {
-                            Some(Keycode::Left) => {
-                                field.select_left();
-                                dirty = true;
-                            },
-                            Some(Keycode::Right) => {
-                                field.select_right();
-                                dirty = true;
-                            },
-                            Some(Keycode::Up) => {
-                                field.select_up();
-                                dirty = true;
-                            },
-                            Some(Keycode::Down) => {
-                                field.select_down();
-                                dirty = true;
-                            },
-                            _ => {},
-                        }
-                    } else if keymod == RGUIMOD || keymod == LGUIMOD {
-                        match keycode {
-                            Some(Keycode::C) => {
-                                let selection = field.selected_text();
-                                clipboard = String::from(&selection[..]);
-                            },
-                            Some(Keycode::X) => {
-                                let selection = field.selected_text();
-                                field.delete_selection();
-                                clipboard = String::from(&selection[..]);
-                            },
-                            Some(Keycode::V) => {
-                                field.insert(&clipboard);
-                            },
-                            Some(Keycode::Return) => {
-                                println!("Text:");
-                                println!("{}", clipboard);
-                            }
-                            _ => {},
-                        }
+                            editor.update_primary();
+                        },
+                        KeyResult::Pending => {},
+                        KeyResult::Unbound => {
+                            println!("Key down: {:?}", keycode);
+                        },
                     }
-                    
                 },
                 Event::TextInput { text, ..} => {
                     println!("Inserting text {:?}", &text);
-                    field.insert(&text);
-                    dirty = true;
-                }
+                    editor.input_text(&text);
+                },
+                Event::TextEditing { text, start, ..} => {
+                    editor.compose(&text, cmp::max(start, 0) as usize);
+                },
+                Event::MouseButtonDown { mouse_btn: MouseButton::Middle, ..} => {
+                    commands.run("paste_primary", editor);
+                },
+                Event::MouseWheel { y: rows, ..} => {
+                    layout.scroll_by(-rows as isize * WHEEL_ROWS);
+                    damage.all();
+                },
+                Event::Window { win_event: WindowEvent::Resized(w, h), ..} => {
+                    screen_width = cmp::max(w, 1) as u32;
+                    screen_height = cmp::max(h, 1) as u32;
+                    // Moves after the resize wrap at the new width
+                    editor.view_width = text_width(&style, 
+                        field_rect(&config, screen_width, screen_height));
+                    // The text texture has the size of the window
+                    target = None;
+                    follow_cursor = true;
+                    damage.all();
+                },
+                Event::Window { win_event: WindowEvent::FocusGained, ..} => {
+                    focused = true;
+                    typed = true;
+                },
+                Event::Window { win_event: WindowEvent::FocusLost, ..} => {
+                    focused = false;
+                },
+                Event::Window { win_event: WindowEvent::Exposed, ..} => {
+                    if supports_targets {
+                        present = true;
+                    } else {
+                        damage.all();
+                    }
+                },
                 _ => {}
             }
         }
+        if editor.quit {
+            break 'mainloop;
+        }
+        
+        // Draw the lines the cursor, selection or composition left and entered
+        if editor.field.cursor != old_cursor || 
+                editor.field.selection_marker != old_marker ||
+                editor.field.wrap_end != old_wrap_end ||
+                editor.field.overwrite != old_overwrite ||
+                editor.composition != old_composition {
+            damage.selection(old_cursor, old_marker);
+            damage.selection(editor.field.cursor, editor.field.selection_marker);
+            follow_cursor = true;
+            typed = true;
+        }
+        // Input shows the caret again and restarts the blinking
+        if typed {
+            blink.reset(Instant::now());
+        }
+        if editor.wrap != old_wrap {
+            follow_cursor = true;
+            damage.all();
+        }
         
         /* Performance stuff
         CPU usage:
@@ -358,21 +1137,174 @@ pub fn main(field: &mut Textfield) {
         When I fill the window with short lines it becomes 18%
         */
         
+        // Reload the config when the file changes
+        if last_config_check.elapsed() >= CONFIG_CHECK_INTERVAL {
+            last_config_check = Instant::now();
+            match watcher.as_mut().and_then(|w| w.poll()) {
+                Some(Ok(mut new_config)) => {
+                    println!("Reloaded the config");
+                    // The window keeps its size, so the margin has to fit it
+                    if ! new_config.window.margin_fits(screen_width, 
+                            screen_height) {
+                        println!("window.margin: the margin leaves no room \
+                            for the text, keeping {}", config.window.margin);
+                        new_config.window.margin = config.window.margin;
+                    }
+                    if new_config.font != config.font || 
+                            new_config.style != config.style ||
+                            new_config.tabs != config.tabs ||
+                            new_config.caret != config.caret ||
+                            new_config.window.clear_color != 
+                                config.window.clear_color {
+                        style = build_style(&ttf, &new_config);
+                        editor.measurer = Box::new(style.text.clone());
+                        editor.font_runs = editor_font_runs(&style);
+                        max_char_width = widest_char_width(&style);
+                    }
+                    if new_config.window.frame_limit != config.window.frame_limit {
+                        limiter = glorious::FrameLimiter::new(
+                            new_config.window.frame_limit);
+                    }
+                    keymap = Keymap::with_overrides(&new_config.keys)
+                        .expect("The config has invalid key bindings");
+                    editor.tabs = new_config.tabs;
+                    if new_config.wrap != config.wrap {
+                        editor.wrap = new_config.wrap;
+                    }
+                    editor.wrap_style = new_config.wrap_style;
+                    blink.set_interval(blink_interval(&new_config), 
+                        Instant::now());
+                    atlas.clear();
+                    layout.reset(editor.field.lines.len());
+                    config = new_config;
+                    damage.all();
+                },
+                Some(Err(e)) => {
+                    println!("Could not reload the config: {}", e);
+                },
+                None => {},
+            }
+        }
+        
+        // Only lay out the lines that edits touched again
+        for edit in editor.field.take_edits() {
+            layout.apply_edit(&edit);
+            if let Some(ref mut hl) = editor.highlighter {
+                hl.apply_edit(&edit);
+            }
+            follow_cursor = true;
+        }
+        
+        let rect = field_rect(&config, screen_width, screen_height);
+        let rows = view_rows(&style, rect);
+        let cursor_line = editor.field.cons_cursor().line;
+        if follow_cursor && layout.scroll_to_line(cursor_line, rows) {
+            damage.all();
+        }
+        
+        // Outline the word at the cursor once the cursor stays on it
+        if style.word_highlight_color.is_some() {
+            let word = editor.field.word_at_cursor();
+            if word != word_at_cursor {
+                word_at_cursor = word;
+                word_since = Instant::now();
+                if outlined_word.take().is_some() {
+                    damage.all();
+                }
+            } else if word_at_cursor != outlined_word 
+                    && word_since.elapsed() >= WORD_HIGHLIGHT_DELAY {
+                outlined_word = word_at_cursor.clone();
+                damage.all();
+            }
+        }
+        
+        // Draw the caret line again when the caret blinks or the focus changes
+        let caret = if ! focused {
+            CaretState::Hollow
+        } else if blink.is_visible(Instant::now()) {
+            CaretState::Solid
+        } else {
+            CaretState::Hidden
+        };
+        if caret != last_caret {
+            damage.line(cursor_line);
+            last_caret = caret;
+        }
+        
+        // Re-highlight the edited lines and redraw from the first one. Edits
+        // can change how many lines the rest of the text is pushed down by.
+        if let Some(line) = editor.field.take_changed_from() {
+            if let Some(ref mut hl) = editor.highlighter {
+                hl.update(&editor.field.lines);
+            }
+            damage.lines_from(line);
+        }
+        
         // Render
-        let rect = Rect::new(64, 64, SCREEN_WIDTH - 128,
-            SCREEN_HEIGHT - 128);
-
-        let wrap_width = Some(200);
-        // Dirty check not used atm to better improve general performance
-        if true { // dirty.
-            renderer.set_draw_color(clear_color);
-            renderer.clear();
-            render_textfield(field, rect, &style, &mut renderer, wrap_width, 
-                Some(max_char_width), &mut text_cache, &mut wrap_cache);
-            renderer.present();
-            dirty = false;
+        if ! damage.is_empty() {
+            let mut frame_damage = damage.take();
+            if supports_targets {
+                let mut render_target = renderer.render_target().unwrap();
+                match target.take() {
+                    Some(texture) => {
+                        render_target.set(texture)
+                            .expect("Could not draw to the text texture");
+                    },
+                    None => {
+                        render_target.create_and_set(PixelFormatEnum::RGBA8888,
+                                screen_width, screen_height)
+                            .expect("Could not create the text texture");
+                        frame_damage.all();
+                    },
+                }
+            } else {
+                // The back buffer is undefined after presenting
+                frame_damage.all();
+            }
+            if frame_damage.is_all() {
+                renderer.set_draw_color(config.window.clear_color);
+                renderer.clear();
+            }
+            
+            let cursor_rect = render_textfield(&editor.field, rect, &style, 
+                &mut renderer, editor.wrap, editor.wrap_style, 
+                Some(max_char_width), 
+                &mut atlas, &mut layout, editor.highlighter.as_ref(),
+                editor.composition.as_ref(), 
+                outlined_word.as_ref().map(|word| &word[..]), caret, 
+                &frame_damage);
+            // The lines drawn can be taller than guessed, hiding the cursor
+            if follow_cursor && layout.scroll_to_line(cursor_line, rows) {
+                damage.all();
+            }
+            follow_cursor = false;
+            if supports_targets {
+                target = renderer.render_target().unwrap().reset()
+                    .expect("Could not draw to the window");
+            }
+            // Keep the input method's candidate window at the cursor
+            if cursor_rect.is_some() && cursor_rect != last_cursor_rect {
+                text_input.set_rect(cursor_rect.as_ref().unwrap());
+                last_cursor_rect = cursor_rect;
+            }
+            present = true;
         }
         
-        limiter.limit();
+        if present {
+            if let Some(ref texture) = target {
+                renderer.copy(texture, None, None);
+            }
+            renderer.present();
+            present = false;
+            limiter.limit();
+        }
+    }
+    
+    // The cache statistics are only shown when tuning the caches
+    if env::var_os("EDITOR_CACHE_STATS").is_some() {
+        println!("Glyph atlas: {} ({} glyphs in {} KiB)", atlas.stats(), 
+            atlas.len(), atlas.texture_bytes() / 1024);
+        println!("Layout: {} lines as {} visual lines", layout.len(), 
+            layout.total_visual_lines());
     }
 }
\ No newline at end of file
@@ -0,0 +1,216 @@
+//! Rendering text fields to images without a window, for screenshots and
+//! image regression tests.
+extern crate sdl2;
+extern crate sdl2_ttf;
+extern crate sdl2_image;
+
+use std::path::Path;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::Renderer;
+use sdl2::surface::Surface;
+use sdl2_image::{LoadSurface, SaveSurface, INIT_PNG};
+use sdl2_ttf::Sdl2TtfContext;
+
+use textfield::Textfield;
+use editor::Composition;
+use config::Config;
+use damage::Damage;
+use caret::CaretState;
+use highlight::Highlighter;
+use render_textfield::{TextfieldStyle, render_textfield, build_style};
+use render_textfield::{widest_char_width, field_rect};
+use layout_cache::LayoutCache;
+use atlas::GlyphAtlas;
+
+/// SDL initialized with the dummy video driver, so that nothing needs a
+/// display.
+pub struct Headless {
+    _sdl: sdl2::Sdl,
+    _image: sdl2_image::Sdl2ImageContext,
+    pub ttf: Sdl2TtfContext,
+}
+
+impl Headless {
+    pub fn init() -> Result<Headless, String> {
+        sdl2::hint::set("SDL_VIDEODRIVER", "dummy");
+        let sdl = sdl2::init()?;
+        sdl.video()?;
+        let image = sdl2_image::init(INIT_PNG)?;
+        let ttf = sdl2_ttf::init().map_err(|e| e.to_string())?;
+        Ok(Headless { _sdl: sdl, _image: image, ttf: ttf })
+    }
+}
+
+/// Renders a text field the way the editor window of the configured size
+/// would show it, onto a software surface.
+pub fn render_to_surface(field: &Textfield, style: &TextfieldStyle,
+        config: &Config, highlighter: Option<&Highlighter>,
+        composition: Option<&Composition>) -> Result<Surface<'static>, String> {
+    let (width, height) = (config.window.width, config.window.height);
+    let surface = Surface::new(width, height, PixelFormatEnum::RGBA8888)?;
+    let mut renderer = Renderer::from_surface(surface)?;
+    renderer.set_draw_color(config.window.clear_color);
+    renderer.clear();
+    let rect = field_rect(config, width, height);
+    let mut damage = Damage::new();
+    damage.all();
+    render_textfield(field, rect, style, &mut renderer, config.wrap,
+        config.wrap_style, Some(widest_char_width(style)), &mut GlyphAtlas::new(), 
+        &mut LayoutCache::new(), highlighter, composition, None,
+        CaretState::Solid, &damage);
+    renderer.present();
+    renderer.into_surface()
+        .ok_or(String::from("The renderer has no surface"))
+}
+
+/// Renders a text field with the style of the config and saves it as a PNG.
+pub fn export_png(field: &Textfield, config: &Config,
+        highlighter: Option<&Highlighter>, path: &Path) -> Result<(), String> {
+    let headless = Headless::init()?;
+    let style = build_style(&headless.ttf, config);
+    let surface = render_to_surface(field, &style, config, highlighter,
+        None)?;
+    surface.save(path)
+}
+
+/// Returns the color of a pixel of a surface.
+pub fn pixel(surface: &Surface, x: u32, y: u32) -> Result<Color, String> {
+    let surface = surface.convert_format(PixelFormatEnum::RGBA8888)?;
+    let offset = y as usize * surface.pitch() as usize + x as usize * 4;
+    Ok(surface.with_lock(|pixels| {
+        let p = &pixels[offset .. offset + 4];
+        // The format is a packed u32 in native byte order
+        let (r, g, b, a) = if cfg!(target_endian = "little") {
+            (p[3], p[2], p[1], p[0])
+        } else {
+            (p[0], p[1], p[2], p[3])
+        };
+        Color::RGBA(r, g, b, a)
+    }))
+}
+
+/// Returns the number of pixels that differ between two surfaces of the same
+/// size.
+pub fn count_differences(a: &Surface, b: &Surface) -> Result<usize, String> {
+    if a.size() != b.size() {
+        return Err(format!("The sizes differ: {:?} and {:?}", a.size(),
+            b.size()));
+    }
+    let a = a.convert_format(PixelFormatEnum::RGBA8888)?;
+    let b = b.convert_format(PixelFormatEnum::RGBA8888)?;
+    let (width, height) = a.size();
+    let (a_pitch, b_pitch) = (a.pitch() as usize, b.pitch() as usize);
+    Ok(a.with_lock(|a_pixels| b.with_lock(|b_pixels| {
+        let row_len = width as usize * 4;
+        (0 .. height as usize).map(|y| {
+            let a_row = &a_pixels[y * a_pitch .. y * a_pitch + row_len];
+            let b_row = &b_pixels[y * b_pitch .. y * b_pitch + row_len];
+            a_row.chunks(4).zip(b_row.chunks(4)).filter(|&(a, b)| a != b)
+                .count()
+        }).sum()
+    })))
+}
+
+/// Compares a surface to the PNG at the given path. The image is written
+/// instead when the `BLESS_GOLDEN` environment variable is set, so that
+/// reviewed changes can update it. A missing image is an error otherwise.
+pub fn compare_golden(surface: &Surface, path: &Path) -> Result<(), String> {
+    if ::std::env::var_os("BLESS_GOLDEN").is_some() {
+        println!("Writing golden image {}", path.display());
+        return surface.save(path);
+    }
+    if ! path.exists() {
+        let actual = path.with_extension("actual.png");
+        let _ = surface.save(&actual);
+        return Err(format!("The golden image {} is missing (see {}, and \
+            run with BLESS_GOLDEN=1 to write it)", path.display(), 
+            actual.display()));
+    }
+    let golden = Surface::from_file(path)?;
+    let differences = count_differences(surface, &golden)?;
+    if differences == 0 {
+        Ok(())
+    } else {
+        let actual = path.with_extension("actual.png");
+        let _ = surface.save(&actual);
+        Err(format!("{} pixels differ from {} (see {})", differences,
+            path.display(), actual.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use cursor::Cursor;
+    use layout::WrapMode;
+    use measure::TextMeasurer;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.window.width = 240;
+        config.window.height = 120;
+        config.window.margin = 0;
+        config.wrap = WrapMode::Width(200);
+        config
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+            .join(format!("{}.png", name))
+    }
+
+    fn check_cursor_placement(headless: &Headless) {
+        let config = config();
+        let style = build_style(&headless.ttf, &config);
+        let (x, y) = (config.style.x_pad, config.style.y_pad + 2);
+        let mut field = Textfield::new("ab");
+        let surface = render_to_surface(&field, &style, &config, None, None)
+            .unwrap();
+        assert_eq!(pixel(&surface, x, y).unwrap(), config.style.cursor_color);
+
+        field.cursor = Cursor::new(0, 2);
+        field.clear_selection();
+        let surface = render_to_surface(&field, &style, &config, None, None)
+            .unwrap();
+        let end_x = x + style.text.measurer.width("ab");
+        assert_eq!(pixel(&surface, end_x, y).unwrap(),
+            config.style.cursor_color);
+        assert!(pixel(&surface, x, y).unwrap() != config.style.cursor_color);
+    }
+
+    fn check_selection_fill(headless: &Headless) {
+        let config = config();
+        let style = build_style(&headless.ttf, &config);
+        let mut field = Textfield::new("aa\nbb");
+        field.cursor = Cursor::new(1, 1);
+        let surface = render_to_surface(&field, &style, &config, None, None)
+            .unwrap();
+        // Above the glyphs of the first line, and past the end of it
+        let y = config.style.y_pad + 1;
+        let x = config.style.x_pad + style.text.measurer.width("aa") + 20;
+        assert_eq!(pixel(&surface, x, y).unwrap(),
+            config.style.selection_color);
+    }
+
+    fn check_golden_wrapped_selection(headless: &Headless) {
+        let config = config();
+        let style = build_style(&headless.ttf, &config);
+        let mut field = Textfield::new(
+            "Some words that are wrapped at the edge\nof the field");
+        field.selection_marker = Cursor::new(0, 5);
+        field.cursor = Cursor::new(1, 2);
+        let surface = render_to_surface(&field, &style, &config, None, None)
+            .unwrap();
+        compare_golden(&surface, &golden_path("wrapped_selection")).unwrap();
+    }
+
+    // SDL can only be initialized from one thread, so the checks share a test
+    #[test]
+    fn test_rendering() {
+        let headless = Headless::init().unwrap();
+        check_cursor_placement(&headless);
+        check_selection_fill(&headless);
+        check_golden_wrapped_selection(&headless);
+    }
+}
@@ -1,6 +1,47 @@
 
+use std::cmp;
+use std::collections::VecDeque;
 use cursor::Cursor;
 use common::StringSliceExt;
+use attributes::{Attributes, AttributedLines, AttrRun};
+use measure::TextMeasurer;
+use layout::{cursor_pos, cursor_pos_before_wrap, row_col_at_x, row_indent};
+use layout::split_wrapped;
+
+/// Lines of a text field that an edit replaced with other lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineEdit {
+    /// The first line the edit changed.
+    pub line: usize,
+    /// How many lines were replaced.
+    pub removed: usize,
+    /// How many lines replaced them.
+    pub inserted: usize,
+}
+
+/// The number of edits that can be undone.
+const MAX_UNDO_STEPS: usize = 1000;
+
+/// Edits that are undone together with the ones of the same kind before
+/// them, when they continue where those left the cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Insert,
+    Delete,
+}
+
+/// Lines that an edit replaced and how many lines replaced them, which undo
+/// puts back together with the cursor and selection from before the edit.
+#[derive(Debug, Clone)]
+struct UndoStep {
+    line: usize,
+    lines: Vec<String>,
+    /// The attribute runs of the lines, if attributes were enabled.
+    runs: Option<Vec<Vec<AttrRun>>>,
+    inserted: usize,
+    cursor: Cursor,
+    marker: Cursor,
+}
 
 /// A field of text
 #[derive(Debug, Clone)]
@@ -8,6 +49,65 @@ pub struct Textfield {
     pub lines: Vec<String>,
     pub cursor: Cursor,
     pub selection_marker: Cursor,
+    /// The first line edited since the changes were last taken.
+    pub changed_from: Option<usize>,
+    /// The edits since they were last taken, in the order they were made.
+    pub edits: Vec<LineEdit>,
+    /// Rich text attributes of the characters, if enabled.
+    pub attributes: Option<AttributedLines>,
+    /// The position of the cursor or selection marker when it is at the end
+    /// of a visual line before a wrap, rather than at the start of the next
+    /// visual line, which is the same position in the text.
+    pub wrap_end: Option<Cursor>,
+    /// Whether inserted text replaces the characters after the cursor.
+    pub overwrite: bool,
+    undo_steps: VecDeque<UndoStep>,
+    redo_steps: Vec<UndoStep>,
+    /// The kind of the last edit and where it left the cursor, if it can be
+    /// continued.
+    last_edit: Option<(EditKind, Cursor)>,
+}
+
+/// Returns whether a character can be part of a word.
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Returns the column ranges where a word occurs in a line as a whole word.
+pub fn word_occurrences(line: &str, word: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    let word: Vec<char> = word.chars().collect();
+    let mut found = Vec::new();
+    if word.is_empty() {
+        return found;
+    }
+    let mut col = 0;
+    while col + word.len() <= chars.len() {
+        let end = col + word.len();
+        if chars[col..end] == word[..]
+                && (col == 0 || ! is_word_char(chars[col - 1]))
+                && (end == chars.len() || ! is_word_char(chars[end])) {
+            found.push((col, end));
+            col = end;
+        } else {
+            col += 1;
+        }
+    }
+    found
+}
+
+/// Lays out the lines of a text field like the view draws them, for moving
+/// by visual lines.
+pub trait LineLayout {
+    /// Calls `f` with the measurer of a line, the byte indices where the line
+    /// wraps and how far its continuation lines are indented.
+    fn lay_out<T, F>(&self, lineno: usize, line: &str, f: F) -> T
+        where F: FnOnce(&dyn TextMeasurer, &[usize], u32) -> T;
+}
+
+/// Returns the column that a visual line of a wrapped line starts at.
+fn row_start(rows: &[&str], row: usize) -> usize {
+    rows[..row].iter().map(|text| text.chars().count()).sum()
 }
 
 impl Textfield {
@@ -17,10 +117,175 @@ impl Textfield {
         let marker = cursor.clone();
         let lines = text.lines().map(|s| s.to_string()).collect();
         Textfield { lines: lines, cursor: cursor,
-            selection_marker: marker
+            selection_marker: marker, changed_from: Some(0),
+            edits: Vec::new(), attributes: None, wrap_end: None,
+            overwrite: false, undo_steps: VecDeque::new(), redo_steps: Vec::new(),
+            last_edit: None,
+        }
+    }
+    
+    /// Starts tracking rich text attributes for the text, all plain.
+    pub fn enable_attributes(&mut self) {
+        if self.attributes.is_none() {
+            self.attributes = Some(AttributedLines::new(&self.lines));
+        }
+    }
+    
+    /// Changes the attributes of the selected text.
+    pub fn apply_attributes<F>(&mut self, change: F) 
+            where F: Fn(&mut Attributes) {
+        if ! self.has_selection() {
+            return;
+        }
+        let (first, last) = self.cons_cursor().order(&self.cons_marker());
+        let count = last.line - first.line + 1;
+        self.record_edit(first.line, count, count);
+        if let Some(ref mut attrs) = self.attributes {
+            attrs.apply(first, last, change);
+        }
+    }
+    
+    /// Removes the text between two positions from the attributes.
+    fn delete_attributes(&mut self, first: Cursor, last: Cursor) {
+        if let Some(ref mut attrs) = self.attributes {
+            attrs.delete(first, last);
+        }
+    }
+    
+    /// Records that the given line and the ones after it have changed.
+    fn mark_changed(&mut self, line: usize) {
+        self.changed_from = Some(match self.changed_from {
+            Some(prev) if prev < line => prev,
+            _ => line,
+        });
+    }
+    
+    /// Records that lines are about to be replaced, saving them for undo.
+    fn record_edit(&mut self, line: usize, removed: usize, inserted: usize) {
+        let step = UndoStep {
+            line: line, lines: self.lines[line .. line + removed].to_vec(),
+            runs: self.attributes.as_ref()
+                .map(|attrs| attrs.line_runs(line, removed)),
+            inserted: inserted, 
+            cursor: self.cursor, marker: self.selection_marker,
+        };
+        self.undo_steps.push_back(step);
+        if self.undo_steps.len() > MAX_UNDO_STEPS {
+            self.undo_steps.pop_front();
+        }
+        self.redo_steps.clear();
+        self.last_edit = None;
+        self.track_edit(line, removed, inserted);
+    }
+    
+    /// Returns whether an edit of the given kind at the cursor continues the
+    /// last edit.
+    fn continues(&self, kind: EditKind) -> bool {
+        ! self.has_selection() && self.last_edit == Some((kind, self.cursor))
+    }
+    
+    /// Finishes an edit of the given kind. An edit that continued the one 
+    /// before it is undone together with it, if it only replaced lines that
+    /// that one left.
+    fn end_edit(&mut self, kind: EditKind, continued: bool) {
+        // An edit that changed nothing recorded no step, and kept the last
+        let recorded = self.last_edit.is_none();
+        let count = self.undo_steps.len();
+        if continued && recorded && count >= 2 {
+            let (prev, last) = (&self.undo_steps[count - 2], 
+                &self.undo_steps[count - 1]);
+            if prev.line <= last.line && last.line + last.lines.len() 
+                    <= prev.line + prev.inserted {
+                self.join_undo();
+            }
+        }
+        self.last_edit = Some((kind, self.cursor));
+    }
+    
+    /// Records that lines were replaced, and that the lines after them moved.
+    fn track_edit(&mut self, line: usize, removed: usize, inserted: usize) {
+        self.mark_changed(line);
+        self.wrap_end = None;
+        self.edits.push(LineEdit { 
+            line: line, removed: removed, inserted: inserted 
+        });
+    }
+    
+    /// Makes the last undo step part of the one before it. The last edit has
+    /// to replace lines that the one before it left.
+    fn join_undo(&mut self) {
+        if let Some(last) = self.undo_steps.pop_back() {
+            if let Some(prev) = self.undo_steps.back_mut() {
+                prev.inserted = prev.inserted + last.inserted - last.lines.len();
+            }
+        }
+    }
+    
+    /// Puts back the lines of an undo step, and returns the step that
+    /// reverts that.
+    fn revert(&mut self, step: UndoStep) -> UndoStep {
+        let count = step.lines.len();
+        let runs = match self.attributes {
+            Some(ref mut attrs) => {
+                // Lines edited before attributes were enabled are plain
+                let lines = &step.lines;
+                let runs = step.runs.unwrap_or_else(|| {
+                    AttributedLines::new(lines).line_runs(0, count)
+                });
+                Some(attrs.replace_lines(step.line, step.inserted, runs))
+            },
+            None => None,
+        };
+        let end = step.line + step.inserted;
+        let replaced = self.lines.drain(step.line .. end).collect();
+        for (i, line) in step.lines.into_iter().enumerate() {
+            self.lines.insert(step.line + i, line);
+        }
+        let inverse = UndoStep {
+            line: step.line, lines: replaced, runs: runs, inserted: count,
+            cursor: self.cursor, marker: self.selection_marker,
+        };
+        self.cursor = step.cursor;
+        self.selection_marker = step.marker;
+        self.last_edit = None;
+        self.track_edit(step.line, step.inserted, count);
+        inverse
+    }
+    
+    /// Reverts the last edit. Returns whether there was one.
+    pub fn undo(&mut self) -> bool {
+        match self.undo_steps.pop_back() {
+            Some(step) => {
+                let inverse = self.revert(step);
+                self.redo_steps.push(inverse);
+                true
+            },
+            None => false,
+        }
+    }
+    
+    /// Makes the last undone edit again. Returns whether there was one.
+    pub fn redo(&mut self) -> bool {
+        match self.redo_steps.pop() {
+            Some(step) => {
+                let inverse = self.revert(step);
+                self.undo_steps.push_back(inverse);
+                true
+            },
+            None => false,
         }
     }
     
+    /// Returns the edits made since the last call.
+    pub fn take_edits(&mut self) -> Vec<LineEdit> {
+        ::std::mem::replace(&mut self.edits, Vec::new())
+    }
+    
+    /// Returns the first line edited since the last call, if any.
+    pub fn take_changed_from(&mut self) -> Option<usize> {
+        self.changed_from.take()
+    }
+    
     /// Returns whether the text-field has text selected or not
     pub fn has_selection(&self) -> bool {
         let cons = self.selection_marker.constrained(&self.lines);
@@ -62,30 +327,120 @@ impl Textfield {
         self.clear_selection();
     }
     
-    /// Moves the cursor up
-    pub fn up(&mut self) {
-        if self.has_selection() {
-            let tmp = self.cursor.clone_min(&self.selection_marker);
-            let mut cons = tmp.constrained(&self.lines);
-            cons.up();
-            self.cursor = cons;
+    /// Returns the position a move starts from: the selection marker when
+    /// selecting, and otherwise the cursor or the end of the selection in
+    /// the direction of the move.
+    fn move_start(&self, forward: bool, select: bool) -> Cursor {
+        if select && self.has_selection() {
+            self.cons_marker()
+        } else if ! select && self.has_selection() {
+            let (first, last) = self.cons_cursor().order(&self.cons_marker());
+            if forward { last } else { first }
         } else {
-            self.cursor.up();
+            self.cons_cursor()
         }
-        self.clear_selection();
     }
     
-    /// Moves the cursor down
-    pub fn down(&mut self) {
-        if self.has_selection() {
-            let tmp = self.cursor.clone_max(&self.selection_marker);
-            let mut cons = tmp.constrained(&self.lines);
-            cons.down(&self.lines);
-            self.cursor = cons;
+    /// Moves the cursor to a position, or the selection marker when
+    /// selecting.
+    fn move_to(&mut self, pos: Cursor, select: bool, at_wrap_end: bool) {
+        if select {
+            self.selection_marker = pos;
         } else {
-            self.cursor.down(&self.lines);
+            self.cursor = pos;
+            self.clear_selection();
         }
-        self.clear_selection();
+        self.wrap_end = if at_wrap_end { Some(pos) } else { None };
+    }
+    
+    /// Returns the visual lines of the line of a position, which of them the
+    /// position is on and its x coordinate there, and how far the visual
+    /// lines after the first are indented.
+    fn visual_pos<'a, L>(&'a self, pos: Cursor, layout: &L)
+            -> (Vec<&'a str>, usize, i32, u32)
+            where L: LineLayout {
+        let line: &'a str = &self.lines[pos.line];
+        let before_wrap = self.wrap_end == Some(pos);
+        layout.lay_out(pos.line, line, |measurer, breaks, indent| {
+            let rows = split_wrapped(line, breaks);
+            let (row, x) = if before_wrap {
+                cursor_pos_before_wrap(pos.col, &rows, &measurer)
+            } else {
+                cursor_pos(pos.col, &rows, &measurer)
+            };
+            (rows, row, x + row_indent(row, indent), indent)
+        })
+    }
+    
+    /// Moves the cursor up or down a visual line, or the selection marker
+    /// when selecting. It goes as close to `goal_x` as it can, or to the x
+    /// coordinate it is at without one. Returns the x coordinate that was
+    /// aimed for.
+    pub fn move_visual<L>(&mut self, down: bool, select: bool, 
+            goal_x: Option<i32>, layout: &L) -> i32
+            where L: LineLayout {
+        let pos = self.move_start(down, select);
+        let (target, at_wrap_end, x) = {
+            let (rows, row, x, _) = self.visual_pos(pos, layout);
+            let x = goal_x.unwrap_or(x);
+            // The line and visual line to go to, where none is the last
+            let destination = if down && row + 1 < rows.len() {
+                Some((pos.line, Some(row + 1)))
+            } else if down && pos.line + 1 < self.lines.len() {
+                Some((pos.line + 1, Some(0)))
+            } else if ! down && row > 0 {
+                Some((pos.line, Some(row - 1)))
+            } else if ! down && pos.line > 0 {
+                Some((pos.line - 1, None))
+            } else {
+                None
+            };
+            match destination {
+                Some((lineno, row)) => {
+                    let line: &str = &self.lines[lineno];
+                    layout.lay_out(lineno, line, |measurer, breaks, indent| {
+                        let rows = split_wrapped(line, breaks);
+                        let row = row.unwrap_or(rows.len() - 1);
+                        let row_x = x - row_indent(row, indent);
+                        let col = row_col_at_x(&rows, row, row_x, &measurer);
+                        let at_end = col == rows[row].chars().count() 
+                            && row + 1 < rows.len();
+                        let col = row_start(&rows, row) + col;
+                        (Cursor::new(lineno, col), at_end, x)
+                    })
+                },
+                // Moving past the first or last line goes to its start or end
+                None => {
+                    let col = if down { 
+                        self.lines[pos.line].chars().count() 
+                    } else { 
+                        0 
+                    };
+                    (Cursor::new(pos.line, col), false, x)
+                },
+            }
+        };
+        self.move_to(target, select, at_wrap_end);
+        x
+    }
+    
+    /// Moves the cursor to the start or end of its visual line, or the
+    /// selection marker when selecting.
+    pub fn move_visual_edge<L>(&mut self, to_end: bool, select: bool,
+            layout: &L)
+            where L: LineLayout {
+        let pos = self.move_start(to_end, select);
+        let (target, at_wrap_end) = {
+            let (rows, row, _, _) = self.visual_pos(pos, layout);
+            let start = row_start(&rows, row);
+            if to_end {
+                let end = start + rows[row].chars().count();
+                (Cursor::new(pos.line, end), row + 1 < rows.len())
+            } else {
+                (Cursor::new(pos.line, start), false)
+            }
+        };
+        self.move_to(target, select, at_wrap_end);
     }
     
     pub fn select_left(&mut self) {
@@ -106,21 +461,29 @@ impl Textfield {
         }
     }
     
-    pub fn select_up(&mut self) {
-        if self.has_selection() {
-            self.selection_marker.up();
-        } else {
-            self.selection_marker = self.cursor.constrained(&self.lines);
-            self.selection_marker.up();
+    /// Returns the identifier that the cursor is in or next to, or the
+    /// selection if it is a whole identifier.
+    pub fn word_at_cursor(&self) -> Option<String> {
+        let (first, last) = self.cons_cursor().order(&self.cons_marker());
+        if first.line != last.line {
+            return None;
         }
-    }
-    
-    pub fn select_down(&mut self) {
-        if self.has_selection() {
-            self.selection_marker.down(&self.lines);
-        } else {
-            self.selection_marker = self.cursor.constrained(&self.lines);
-            self.selection_marker.down(&self.lines);
+        let chars: Vec<char> = self.lines[first.line].chars().collect();
+        let (mut start, mut end) = (first.col, last.col);
+        if start == end {
+            while start > 0 && is_word_char(chars[start - 1]) {
+                start -= 1;
+            }
+            while end < chars.len() && is_word_char(chars[end]) {
+                end += 1;
+            }
+        }
+        let word: String = chars[start..end].iter().cloned().collect();
+        let whole = word_occurrences(&self.lines[first.line], &word)
+            .contains(&(start, end));
+        match word.chars().next() {
+            Some(ch) if whole && ! ch.is_numeric() => Some(word),
+            _ => None,
         }
     }
     
@@ -156,6 +519,9 @@ impl Textfield {
             return;
         }
         let (first, last) = self.cursor.order(&self.selection_marker);
+        self.record_edit(first.line, last.line - first.line + 1, 1);
+        let (cons_first, cons_last) = self.cons_cursor().order(&self.cons_marker());
+        self.delete_attributes(cons_first, cons_last);
         // Same line
         if first.line == last.line {
             let text = {
@@ -189,9 +555,13 @@ impl Textfield {
     /// Delete a character bacward in the text, or the current selection.
     pub fn delete_previous(&mut self) {
         if ! self.has_selection() {
+            let continued = self.continues(EditKind::Delete);
             let cons = self.cons_cursor();
             // Delete within same line
             if cons.col > 0 {
+                self.record_edit(cons.line, 1, 1);
+                let prev = Cursor::new(cons.line, cons.col - 1);
+                self.delete_attributes(prev, cons);
                 let text = {
                     let ref line = self.lines[self.cursor.line];
                     let mut new_line = String::new();
@@ -207,14 +577,18 @@ impl Textfield {
             // Merge with previous (if any)
             } else {
                 if self.cursor.line != 0 {
+                    let prev_line = self.cursor.line - 1;
+                    self.record_edit(prev_line, 2, 1);
+                    let prev_len = self.lines[prev_line].chars().count();
+                    self.delete_attributes(Cursor::new(prev_line, prev_len), 
+                        Cursor::new(self.cursor.line, 0));
                     let line = self.lines.remove(self.cursor.line);
-                    let prev_len = self.lines[self.cursor.line - 1]
-                        .chars().count();
                     self.lines[self.cursor.line - 1].push_str(&line);
                     self.cursor = Cursor::new(self.cursor.line - 1, prev_len);
                     self.clear_selection();
                 }
             }
+            self.end_edit(EditKind::Delete, continued);
         } else {
             self.delete_selection();
         }
@@ -223,10 +597,14 @@ impl Textfield {
     /// Delete a character forward in the text, or the current selection.
     pub fn delete_next(&mut self) {
         if ! self.has_selection() {
+            let continued = self.continues(EditKind::Delete);
             let cons = self.cons_cursor();
             let line_len = self.lines[cons.line].chars().count();
             // Delete within same line
             if cons.col != line_len {
+                self.record_edit(cons.line, 1, 1);
+                let next = Cursor::new(cons.line, cons.col + 1);
+                self.delete_attributes(cons, next);
                 let text = {
                     let ref line = self.lines[self.cursor.line];
                     let mut new_line = String::new();
@@ -241,21 +619,53 @@ impl Textfield {
             // Merge with next (if any)
             } else {
                 if self.cursor.line != (self.lines.len() - 1) {
+                    self.record_edit(cons.line, 2, 1);
+                    self.delete_attributes(cons, Cursor::new(cons.line + 1, 0));
                     let line = self.lines.remove(self.cursor.line + 1);
                     self.lines[self.cursor.line].push_str(&line);
                     self.cursor = cons;
                     self.clear_selection();
                 }
             }
-        
+            self.end_edit(EditKind::Delete, continued);
         } else {
             self.delete_selection();
         }
     }
     
+    /// Inserts text at the cursor, replacing the selection. In overwrite
+    /// mode the text replaces as many characters after the cursor as its
+    /// first line has, but not the end of the line.
     pub fn insert(&mut self, text: &str) {
+        let before = (self.cursor, self.selection_marker);
+        let continued = self.continues(EditKind::Insert);
+        if self.overwrite && ! self.has_selection() {
+            let cons = self.cons_cursor();
+            let typed = text.split('\n').next().unwrap_or("").chars().count();
+            let rest = self.lines[cons.line].chars().count() - cons.col;
+            self.cursor = cons;
+            self.selection_marker = Cursor::new(cons.line, 
+                cons.col + cmp::min(typed, rest));
+        }
+        let replacing = self.has_selection();
         self.delete_selection();
+        if text.is_empty() {
+            return;
+        }
         let start = self.cursor.line;
+        self.record_edit(start, 1, text.split('\n').count());
+        // Undo puts back the replaced text together with the insert
+        if replacing {
+            self.join_undo();
+            if let Some(step) = self.undo_steps.back_mut() {
+                step.cursor = before.0;
+                step.marker = before.1;
+            }
+        }
+        let at = self.cons_cursor();
+        if let Some(ref mut attrs) = self.attributes {
+            attrs.insert(at, text);
+        }
         
         let left = String::from(self.lines[start].slice_until(self.cursor.col));
         let right = String::from(self.lines[start].slice_after(self.cursor.col));
@@ -265,7 +675,7 @@ impl Textfield {
             if num == 0 {
                 self.lines[start].push_str(line);
             } else {
-                self.lines.insert(start + num + 1, String::from(line));
+                self.lines.insert(start + num, String::from(line));
             }
             num_lines += 1;
         }
@@ -286,5 +696,93 @@ impl Textfield {
         
         // Add the last part to the last line
         self.lines[start + num_lines - 1].push_str(&right);
+        self.end_edit(EditKind::Insert, continued);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cursor::Cursor;
+
+    #[test]
+    fn test_word_occurrences() {
+        assert_eq!(word_occurrences("foo foobar foo_ (foo)", "foo"), 
+            vec![(0, 3), (17, 20)]);
+        assert_eq!(word_occurrences("ab", ""), vec![]);
+    }
+
+    #[test]
+    fn test_insert_lines() {
+        let mut field = Textfield::new("ab\ncd");
+        field.cursor = Cursor::new(0, 1);
+        field.clear_selection();
+        field.insert("x\ny\nz");
+        assert_eq!(field.lines, vec!["ax", "y", "zb", "cd"]);
+        assert_eq!(field.cursor, Cursor::new(2, 1));
+    }
+
+    #[test]
+    fn test_undo_joins_typing() {
+        let mut field = Textfield::new("ab");
+        field.insert("x");
+        field.insert("\n");
+        field.insert("y");
+        field.insert("z");
+        field.delete_previous();
+        field.delete_previous();
+        assert_eq!(field.lines, vec!["x", "ab"]);
+        assert!(field.undo());
+        assert_eq!(field.lines, vec!["x", "yzab"]);
+        assert!(field.undo());
+        assert_eq!(field.lines, vec!["ab"]);
+        assert!(! field.undo());
+        // Moving the cursor starts a new step
+        field.insert("x");
+        field.cursor = Cursor::new(0, 0);
+        field.clear_selection();
+        field.insert("y");
+        assert!(field.undo());
+        assert_eq!(field.lines, vec!["xab"]);
+        // Deleting nothing doesn't join the steps before it
+        field.cursor = Cursor::new(0, 1);
+        field.clear_selection();
+        field.delete_previous();
+        field.delete_previous();
+        assert!(field.undo());
+        assert_eq!(field.lines, vec!["xab"]);
+    }
+
+    #[test]
+    fn test_undo_depth() {
+        let mut field = Textfield::new("a");
+        for _ in 0 .. MAX_UNDO_STEPS + 5 {
+            field.insert("x");
+            field.left();
+        }
+        let mut undone = 0;
+        while field.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO_STEPS);
+        assert_eq!(field.lines, vec!["xxxxxa"]);
+    }
+
+    #[test]
+    fn test_word_at_cursor() {
+        let mut field = Textfield::new("let x_1 = y1 + 2;");
+        assert_eq!(field.word_at_cursor(), Some(String::from("let")));
+        field.cursor = Cursor::new(0, 7);
+        field.clear_selection();
+        assert_eq!(field.word_at_cursor(), Some(String::from("x_1")));
+        field.cursor = Cursor::new(0, 16);
+        field.clear_selection();
+        assert_eq!(field.word_at_cursor(), None);
+        // A selection has to be a whole word
+        field.selection_marker = Cursor::new(0, 10);
+        field.cursor = Cursor::new(0, 12);
+        assert_eq!(field.word_at_cursor(), Some(String::from("y1")));
+        field.cursor = Cursor::new(0, 11);
+        assert_eq!(field.word_at_cursor(), None);
     }
 }